@@ -1,14 +1,114 @@
-use crate::db::ActionLog;
+use crate::db::{AccessLog, ActionLog, ImportSummary, LogFilters, LogFormat, LogIntegrity, LogOrigin, LogPage, LogRetention, PruneSummary, VacuumResult};
 use crate::error::Result;
-use tauri::State;
+use tauri::{AppHandle, Manager, State};
 use crate::commands::file_ops::AppState;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+use std::time::Duration;
+
+pub const LOG_RETENTION_KEY: &str = "log_retention";
+
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60 * 60);
 
 #[tauri::command]
 pub async fn get_recent_logs(limit: usize, state: State<'_, AppState>) -> Result<Vec<ActionLog>> {
-    state.db.get_recent_logs(limit)
+    state.db.run(move |db| db.get_recent_logs(limit)).await
+}
+
+/// Pages through the action log, newest first. Pass the returned
+/// `next_cursor` back to get the following page.
+#[tauri::command]
+pub async fn query_logs(filters: Option<LogFilters>, cursor: Option<i64>, limit: Option<usize>, state: State<'_, AppState>) -> Result<LogPage> {
+    let filters = filters.unwrap_or_default();
+    state.db.run(move |db| db.query_logs(&filters, cursor, limit)).await
 }
 
+/// Writes the rows matching `filters` to `path`, which must be inside a
+/// writable root, and returns how many were written.
+#[tauri::command]
+pub async fn export_logs(path: String, format: LogFormat, filters: Option<LogFilters>, state: State<'_, AppState>) -> Result<usize> {
+    let file = BufWriter::new(state.sandbox().locate(Path::new(&path))?.create()?);
+    let filters = filters.unwrap_or_default();
+    state.db.run(move |db| db.export_logs(&filters, format, file)).await
+}
+
+/// Merges a log exported on another machine, skipping rows already present.
+#[tauri::command]
+pub async fn import_logs(path: String, format: LogFormat, state: State<'_, AppState>) -> Result<ImportSummary> {
+    let file = BufReader::new(state.sandbox().locate(Path::new(&path))?.open()?);
+    state.db.run(move |db| db.import_logs(format, file)).await
+}
+
+/// Reports the first row of the action log whose hash chain is broken.
+#[tauri::command]
+pub async fn verify_log_integrity(state: State<'_, AppState>) -> Result<LogIntegrity> {
+    state.db.run(|db| db.verify_log_integrity()).await
+}
+
+/// Logs an entry on behalf of the frontend. It is always stored as a
+/// client row, so it can't pass for, or be undone like, a backend action.
 #[tauri::command]
 pub async fn db_log(entry: ActionLog, state: State<'_, AppState>) -> Result<i64> {
-    state.db.log_action(entry)
-}
\ No newline at end of file
+    let entry = ActionLog {
+        id: None,
+        undone: false,
+        origin: LogOrigin::Client,
+        ..entry
+    };
+    state.db.run(move |db| db.log_action(entry)).await
+}
+
+/// Successful read-only events such as directory listings, newest first.
+#[tauri::command]
+pub async fn get_access_logs(limit: usize, state: State<'_, AppState>) -> Result<Vec<AccessLog>> {
+    state.db.run(move |db| db.get_access_logs(limit)).await
+}
+
+#[tauri::command]
+pub async fn get_log_retention(state: State<'_, AppState>) -> Result<LogRetention> {
+    Ok(state.log_retention.lock().unwrap().clone())
+}
+
+/// Saves the retention settings and prunes both logs to the new limits.
+#[tauri::command]
+pub async fn set_log_retention(retention: LogRetention, app: AppHandle, state: State<'_, AppState>) -> Result<()> {
+    let stored = retention.clone();
+    state.db.run(move |db| db.set_setting(LOG_RETENTION_KEY, &stored)).await?;
+    *state.log_retention.lock().unwrap() = retention;
+    prune_in_background(&app);
+    Ok(())
+}
+
+/// Prunes both logs right away and reports how many rows went.
+#[tauri::command]
+pub async fn prune_logs(state: State<'_, AppState>) -> Result<PruneSummary> {
+    let retention = state.log_retention.lock().unwrap().clone();
+    state.db.run(move |db| db.prune_logs(&retention)).await
+}
+
+/// Compacts the database file after pruning.
+#[tauri::command]
+pub async fn vacuum_db(state: State<'_, AppState>) -> Result<VacuumResult> {
+    state.db.run(|db| db.vacuum()).await
+}
+
+/// Periodically enforces the log retention settings for as long as the app runs.
+pub fn spawn_log_pruner(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        let mut interval = tokio::time::interval(PRUNE_INTERVAL);
+        loop {
+            interval.tick().await;
+            prune_in_background(&app);
+        }
+    });
+}
+
+/// Runs a prune on a blocking worker without waiting for it.
+pub fn prune_in_background(app: &AppHandle) {
+    let app = app.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let state = app.state::<AppState>();
+        let retention = state.log_retention.lock().unwrap().clone();
+        let _ = state.db.prune_logs(&retention);
+    });
+}
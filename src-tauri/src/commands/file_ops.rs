@@ -1,80 +1,83 @@
-use crate::db::{ActionLog, Database};
+use crate::db::{ActionLog, Database, LogOrigin, LogRetention};
 use crate::error::Result;
-use crate::fs::{self, FileItem, FileStat};
-use crate::fs::operations::{self, DirResult, RenameResult, BatchResult, UndoResult};
+use crate::fs::FileStat;
+use crate::fs::conflict::ConflictPolicy;
+use crate::fs::operations::{self, DirPage, DirResult, ListOptions, RenameResult, BatchResult, NoProgress, SymlinkPolicy, UndoItemResult, UndoResult};
+use crate::fs::sandbox::{Access, AllowedRoot, Location, RootSettings, Sandbox};
 use crate::fs::trash::TrashManager;
 use crate::fs::validators;
+use crate::index::Indexer;
+use crate::commands::search_ops::refresh_index_in_background;
+use crate::jobs::{JobHandle, JobInfo, JobManager};
+use crate::watch::WatchManager;
 use chrono::Utc;
-use std::path::PathBuf;
-use std::sync::Mutex;
-use tauri::State;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tauri::{AppHandle, Manager, State};
+use uuid::Uuid;
+
+/// Settings key of `RootSettings::strict`; the roots have their own table.
+pub const STRICT_ROOTS_KEY: &str = "strict_roots";
 
 pub struct AppState {
     pub db: Database,
-    pub allowed_roots: Mutex<Vec<PathBuf>>,
+    pub allowed_roots: Mutex<RootSettings>,
+    /// Handles on `allowed_roots`, opened again only when the roots change
+    pub sandbox: Mutex<Arc<Sandbox>>,
     pub trash_manager: TrashManager,
+    pub jobs: JobManager,
+    pub indexer: Indexer,
+    pub watcher: WatchManager,
+    pub log_retention: Mutex<LogRetention>,
+}
+
+impl AppState {
+    /// Handles on the allowed roots that every file operation goes through.
+    pub fn sandbox(&self) -> Arc<Sandbox> {
+        self.sandbox.lock().unwrap().clone()
+    }
 }
 
 #[tauri::command]
-pub async fn list_dir(path: String, state: State<'_, AppState>) -> Result<Vec<FileItem>> {
+pub async fn list_dir(path: String, options: Option<ListOptions>, state: State<'_, AppState>) -> Result<DirPage> {
     let path = PathBuf::from(&path);
     
-    // Validate path is within allowed roots
-    let roots = state.allowed_roots.lock().unwrap();
-    if !roots.is_empty() && !fs::is_path_safe(&path, &roots) {
-        return Err(crate::error::AppError::NotAllowed(
-            "Access to this directory is not allowed".to_string()
-        ));
-    }
-
-    let items = operations::list_directory(&path)?;
+    // Fails unless the path is within the allowed roots
+    let page = log_failure(&state, "LIST_DIR", Some(&path), None, state
+        .sandbox()
+        .locate(&path)
+        .and_then(|dir| operations::list_directory(&dir, &options.unwrap_or_default()))).await?;
     
-    // Log the operation
-    let _ = state.db.log_action(ActionLog {
-        id: None,
-        timestamp: Utc::now().to_rfc3339(),
-        action: "LIST_DIR".to_string(),
-        src_path: Some(path.to_string_lossy().to_string()),
-        dst_path: None,
-        status: "SUCCESS".to_string(),
-        message: None,
-        batch_id: None,
-    });
+    // Successful listings go to the access log, not the action log, and
+    // never wait for it
+    let access = state.log_retention.lock().unwrap().access;
+    let db = state.db.clone();
+    tauri::async_runtime::spawn_blocking(move || db.log_access("LIST_DIR", &path.to_string_lossy(), access));
 
-    Ok(items)
+    Ok(page)
 }
 
 #[tauri::command]
 pub async fn stat_path(path: String, state: State<'_, AppState>) -> Result<FileStat> {
     let path = PathBuf::from(&path);
     
-    let roots = state.allowed_roots.lock().unwrap();
-    if !roots.is_empty() && !fs::is_path_safe(&path, &roots) {
-        return Err(crate::error::AppError::NotAllowed(
-            "Access to this path is not allowed".to_string()
-        ));
-    }
-
-    operations::get_file_stat(&path)
+    log_failure(&state, "STAT", Some(&path), None, state
+        .sandbox()
+        .locate(&path)
+        .and_then(|location| operations::get_file_stat(&location))).await
 }
 
 #[tauri::command]
-pub async fn make_dir(base: String, name: String, state: State<'_, AppState>) -> Result<DirResult> {
-    validators::validate_file_name(&name)?;
-    
+pub async fn make_dir(base: String, name: String, app: AppHandle, state: State<'_, AppState>) -> Result<DirResult> {
     let base_path = PathBuf::from(&base);
+    let dest_path = base_path.join(&name);
     
-    let roots = state.allowed_roots.lock().unwrap();
-    if !roots.is_empty() && !fs::is_path_safe(&base_path, &roots) {
-        return Err(crate::error::AppError::NotAllowed(
-            "Access to this directory is not allowed".to_string()
-        ));
-    }
-
-    let result = operations::create_directory(&base_path, &name)?;
+    let result = log_failure(&state, "CREATE_DIR", None, Some(&dest_path), validators::validate_file_name(&name)
+        .and_then(|_| state.sandbox().locate(&base_path))
+        .and_then(|base_dir| operations::create_directory(&base_dir, &name))).await?;
     
     // Log the operation
-    let _ = state.db.log_action(ActionLog {
+    let log = ActionLog {
         id: None,
         timestamp: Utc::now().to_rfc3339(),
         action: "CREATE_DIR".to_string(),
@@ -82,29 +85,29 @@ pub async fn make_dir(base: String, name: String, state: State<'_, AppState>) ->
         dst_path: Some(result.path.clone()),
         status: "SUCCESS".to_string(),
         message: None,
-        batch_id: None,
-    });
+        batch_id: Some(Uuid::new_v4().to_string()),
+        undone: false,
+        origin: LogOrigin::Backend,
+        error_code: None,
+    };
+    let _ = state.db.run(move |db| db.log_action(log)).await;
+
+    refresh_index_in_background(&app, vec![PathBuf::from(&result.path)]);
 
     Ok(result)
 }
 
 #[tauri::command]
-pub async fn rename_path(src: String, new_name: String, state: State<'_, AppState>) -> Result<RenameResult> {
-    validators::validate_file_name(&new_name)?;
-    
+pub async fn rename_path(src: String, new_name: String, app: AppHandle, state: State<'_, AppState>) -> Result<RenameResult> {
     let src_path = PathBuf::from(&src);
+    let dest_path = src_path.with_file_name(&new_name);
     
-    let roots = state.allowed_roots.lock().unwrap();
-    if !roots.is_empty() && !fs::is_path_safe(&src_path, &roots) {
-        return Err(crate::error::AppError::NotAllowed(
-            "Access to this path is not allowed".to_string()
-        ));
-    }
-
-    let result = operations::rename_item(&src_path, &new_name)?;
+    let result = log_failure(&state, "RENAME", Some(&src_path), Some(&dest_path), validators::validate_file_name(&new_name)
+        .and_then(|_| state.sandbox().locate(&src_path))
+        .and_then(|src_location| operations::rename_item(&src_location, &new_name))).await?;
     
     // Log the operation
-    let _ = state.db.log_action(ActionLog {
+    let log = ActionLog {
         id: None,
         timestamp: Utc::now().to_rfc3339(),
         action: "RENAME".to_string(),
@@ -112,197 +115,318 @@ pub async fn rename_path(src: String, new_name: String, state: State<'_, AppStat
         dst_path: Some(result.new_path.clone()),
         status: "SUCCESS".to_string(),
         message: None,
-        batch_id: None,
-    });
+        batch_id: Some(Uuid::new_v4().to_string()),
+        undone: false,
+        origin: LogOrigin::Backend,
+        error_code: None,
+    };
+    let _ = state.db.run(move |db| db.log_action(log)).await;
+
+    refresh_index_in_background(&app, vec![PathBuf::from(&result.old_path), PathBuf::from(&result.new_path)]);
 
     Ok(result)
 }
 
 #[tauri::command]
-pub async fn move_paths(src_paths: Vec<String>, dest_dir: String, state: State<'_, AppState>) -> Result<BatchResult> {
+pub async fn move_paths(src_paths: Vec<String>, dest_dir: String, conflict_policy: Option<ConflictPolicy>, symlink_policy: Option<SymlinkPolicy>, job_id: Option<String>, app: AppHandle, state: State<'_, AppState>) -> Result<BatchResult> {
     let dest_path = PathBuf::from(&dest_dir);
-    
-    let roots = state.allowed_roots.lock().unwrap();
-    if !roots.is_empty() && !fs::is_path_safe(&dest_path, &roots) {
-        return Err(crate::error::AppError::NotAllowed(
-            "Access to destination directory is not allowed".to_string()
-        ));
-    }
-
     let src_paths: Vec<PathBuf> = src_paths
         .into_iter()
         .map(PathBuf::from)
         .collect();
 
-    // Validate all source paths
-    for path in &src_paths {
-        if !roots.is_empty() && !fs::is_path_safe(path, &roots) {
-            return Err(crate::error::AppError::NotAllowed(
-                format!("Access to {} is not allowed", path.display())
-            ));
+    let result = async {
+        // The destination doesn't have to exist yet, only to be inside a root
+        let sandbox = state.sandbox();
+        sandbox.locate(&dest_path)?.require(Access::Write)?;
+
+        // Validate all source paths; moving them out of their root also needs Delete
+        for path in &src_paths {
+            sandbox.locate(path)?.require(Access::Write)?;
         }
-    }
 
-    let result = operations::move_items(src_paths.clone(), &dest_path)?;
-    
-    // Log the batch operation
-    for (i, src) in src_paths.iter().enumerate() {
-        if result.results[i].success {
-            let _ = state.db.log_action(ActionLog {
-                id: None,
-                timestamp: Utc::now().to_rfc3339(),
-                action: "MOVE".to_string(),
-                src_path: Some(src.to_string_lossy().to_string()),
-                dst_path: Some(dest_dir.clone()),
-                status: "SUCCESS".to_string(),
-                message: None,
-                batch_id: Some(result.batch_id.clone()),
-            });
+        let policy = conflict_policy.unwrap_or_default();
+        let links = symlink_policy.unwrap_or_default();
+        let dest_path = dest_path.clone();
+        let src_paths = src_paths.clone();
+        let trash_manager = state.trash_manager.clone();
+        run_batch_job("MOVE", job_id, &app, &state, move |job| {
+            operations::move_items(&sandbox, src_paths, &dest_path, policy, &trash_manager, links, job)
+        }).await
+    }.await;
+    let result = log_batch_failure(&state, "MOVE", &src_paths, Some(&dest_path), result).await?;
+
+    log_batch(&state, "MOVE", &result).await;
+
+    refresh_index_in_background(&app, touched_paths(&result));
+
+    Ok(result)
+}
+
+#[tauri::command]
+pub async fn copy_paths(src_paths: Vec<String>, dest_dir: String, conflict_policy: Option<ConflictPolicy>, symlink_policy: Option<SymlinkPolicy>, job_id: Option<String>, app: AppHandle, state: State<'_, AppState>) -> Result<BatchResult> {
+    let dest_path = PathBuf::from(&dest_dir);
+    let src_paths: Vec<PathBuf> = src_paths
+        .into_iter()
+        .map(PathBuf::from)
+        .collect();
+
+    let result = async {
+        // The destination doesn't have to exist yet, only to be inside a root
+        let sandbox = state.sandbox();
+        sandbox.locate(&dest_path)?.require(Access::Write)?;
+
+        // Validate all source paths
+        for path in &src_paths {
+            sandbox.locate(path)?;
         }
-    }
+
+        let policy = conflict_policy.unwrap_or_default();
+        let links = symlink_policy.unwrap_or_default();
+        let dest_path = dest_path.clone();
+        let src_paths = src_paths.clone();
+        let trash_manager = state.trash_manager.clone();
+        run_batch_job("COPY", job_id, &app, &state, move |job| {
+            operations::copy_items(&sandbox, src_paths, &dest_path, policy, &trash_manager, links, job)
+        }).await
+    }.await;
+    let result = log_batch_failure(&state, "COPY", &src_paths, Some(&dest_path), result).await?;
+
+    log_batch(&state, "COPY", &result).await;
+
+    refresh_index_in_background(&app, touched_paths(&result));
 
     Ok(result)
 }
 
 #[tauri::command]
-pub async fn soft_delete(paths: Vec<String>, state: State<'_, AppState>) -> Result<BatchResult> {
+pub async fn soft_delete(paths: Vec<String>, conflict_policy: Option<ConflictPolicy>, symlink_policy: Option<SymlinkPolicy>, job_id: Option<String>, app: AppHandle, state: State<'_, AppState>) -> Result<BatchResult> {
     let paths: Vec<PathBuf> = paths
         .into_iter()
         .map(PathBuf::from)
         .collect();
 
-    let roots = state.allowed_roots.lock().unwrap();
-    
-    // Validate all paths
-    for path in &paths {
-        if !roots.is_empty() && !fs::is_path_safe(path, &roots) {
-            return Err(crate::error::AppError::NotAllowed(
-                format!("Access to {} is not allowed", path.display())
-            ));
+    let result = async {
+        let sandbox = state.sandbox();
+        
+        // Validate all paths
+        for path in &paths {
+            sandbox.locate(path)?.require(Access::Delete)?;
         }
-    }
 
-    let result = state.trash_manager.soft_delete(paths.clone())?;
+        // Same-named items must never silently replace each other in the trash
+        let policy = conflict_policy.unwrap_or(ConflictPolicy::KeepBoth);
+        let links = symlink_policy.unwrap_or_default();
+        let paths = paths.clone();
+        let trash_manager = state.trash_manager.clone();
+        run_batch_job("DELETE", job_id, &app, &state, move |job| {
+            trash_manager.soft_delete(&sandbox, paths, policy, links, job)
+        }).await
+    }.await;
+    let result = log_batch_failure(&state, "DELETE", &paths, None, result).await?;
     
-    // Log the batch operation
-    for (i, path) in paths.iter().enumerate() {
-        if result.results[i].success {
-            let _ = state.db.log_action(ActionLog {
+    log_batch(&state, "DELETE", &result).await;
+
+    refresh_index_in_background(&app, touched_paths(&result));
+
+    // Large deletes may push the trash over its quota
+    crate::commands::trash_ops::sweep_in_background(&app);
+
+    Ok(result)
+}
+
+#[tauri::command]
+pub async fn list_jobs(state: State<'_, AppState>) -> Result<Vec<JobInfo>> {
+    Ok(state.jobs.list())
+}
+
+#[tauri::command]
+pub async fn cancel_job(job_id: String, state: State<'_, AppState>) -> Result<()> {
+    log_failure(&state, "CANCEL_JOB", None, None, state.jobs.cancel(&job_id)).await
+}
+
+/// Records a command that failed as a whole, with the error's code and
+/// message, and passes its result through.
+async fn log_failure<T: Send>(state: &AppState, action: &str, src_path: Option<&Path>, dst_path: Option<&Path>, result: Result<T>) -> Result<T> {
+    if let Err(e) = &result {
+        let log = ActionLog {
+            id: None,
+            timestamp: Utc::now().to_rfc3339(),
+            action: action.to_string(),
+            src_path: src_path.map(|p| p.to_string_lossy().to_string()),
+            dst_path: dst_path.map(|p| p.to_string_lossy().to_string()),
+            status: "ERROR".to_string(),
+            message: Some(e.to_string()),
+            batch_id: None,
+            undone: false,
+            origin: LogOrigin::Backend,
+            error_code: Some(e.error_code().to_string()),
+        };
+        let _ = state.db.run(move |db| db.log_action(log)).await;
+    }
+    result
+}
+
+/// Records a batch command that failed as a whole with one error row per
+/// requested path, all under the same batch id, and passes its result through.
+async fn log_batch_failure<T: Send>(state: &AppState, action: &str, src_paths: &[PathBuf], dst_path: Option<&Path>, result: Result<T>) -> Result<T> {
+    if src_paths.is_empty() {
+        return log_failure(state, action, None, dst_path, result).await;
+    }
+
+    if let Err(e) = &result {
+        let batch_id = Uuid::new_v4().to_string();
+        let logs: Vec<ActionLog> = src_paths
+            .iter()
+            .map(|src_path| ActionLog {
+                id: None,
+                timestamp: Utc::now().to_rfc3339(),
+                action: action.to_string(),
+                src_path: Some(src_path.to_string_lossy().to_string()),
+                dst_path: dst_path.map(|p| p.to_string_lossy().to_string()),
+                status: "ERROR".to_string(),
+                message: Some(e.to_string()),
+                batch_id: Some(batch_id.clone()),
+                undone: false,
+                origin: LogOrigin::Backend,
+                error_code: Some(e.error_code().to_string()),
+            })
+            .collect();
+
+        let _ = state.db.run(move |db| {
+            for log in logs {
+                db.log_action(log)?;
+            }
+            Ok(())
+        }).await;
+    }
+    result
+}
+
+/// Logs the applied items of a batch as successes and the failed ones as
+/// errors. Item paths rather than the selection, since a followed link acts
+/// on its target. An entry an overwrite replaced is logged as trashed just
+/// before the item, so undoing the batch brings it back afterwards.
+async fn log_batch(state: &AppState, action: &str, result: &BatchResult) {
+    let mut logs = Vec::new();
+    for item in &result.results {
+        if let (Some(dest_path), Some(replaced_path)) = (&item.dest_path, &item.replaced_path) {
+            logs.push(ActionLog {
                 id: None,
                 timestamp: Utc::now().to_rfc3339(),
                 action: "DELETE".to_string(),
-                src_path: Some(path.to_string_lossy().to_string()),
-                dst_path: None,
+                src_path: Some(dest_path.clone()),
+                dst_path: Some(replaced_path.clone()),
                 status: "SUCCESS".to_string(),
-                message: None,
+                message: Some(format!("Replaced by {}", item.path)),
                 batch_id: Some(result.batch_id.clone()),
+                undone: false,
+                origin: LogOrigin::Backend,
+                error_code: None,
             });
         }
+
+        let (status, message) = if item.was_applied() {
+            ("SUCCESS", item.resolution.log_message())
+        } else if !item.success {
+            ("ERROR", item.message.clone())
+        } else {
+            continue;
+        };
+
+        logs.push(ActionLog {
+            id: None,
+            timestamp: Utc::now().to_rfc3339(),
+            action: action.to_string(),
+            src_path: Some(item.path.clone()),
+            dst_path: item.dest_path.clone(),
+            status: status.to_string(),
+            message,
+            batch_id: Some(result.batch_id.clone()),
+            undone: false,
+            origin: LogOrigin::Backend,
+            error_code: item.error_code.clone(),
+        });
     }
 
-    Ok(result)
+    let _ = state.db.run(move |db| {
+        for log in logs {
+            db.log_action(log)?;
+        }
+        Ok(())
+    }).await;
+}
+
+/// Sources and destinations of the items a batch actually changed.
+fn touched_paths(result: &BatchResult) -> Vec<PathBuf> {
+    result
+        .results
+        .iter()
+        .filter(|item| item.was_applied())
+        .flat_map(|item| std::iter::once(&item.path).chain(item.dest_path.iter()))
+        .map(PathBuf::from)
+        .collect()
+}
+
+/// Runs a batch operation as a cancellable job on a blocking worker thread,
+/// so large trees don't tie up the async runtime. The job runs under
+/// `job_id` when the caller picked one, so it can be cancelled by id while
+/// the command is still running.
+async fn run_batch_job<F>(kind: &str, job_id: Option<String>, app: &AppHandle, state: &AppState, op: F) -> Result<BatchResult>
+where
+    F: FnOnce(&JobHandle) -> Result<BatchResult> + Send + 'static,
+{
+    let job = state.jobs.start(kind, job_id, app)?;
+    let worker = job.clone();
+
+    let result = tauri::async_runtime::spawn_blocking(move || op(&worker))
+        .await
+        .map_err(|e| crate::error::AppError::Unknown(e.to_string()))
+        .and_then(|result| result)
+        .map(|batch| BatchResult { job_id: Some(job.id()), ..batch });
+
+    job.finish(
+        result
+            .as_ref()
+            .map(|batch| Some(batch.batch_id.clone()))
+            .map_err(|e| e.to_string()),
+    );
+    result
+}
+
+/// Runs `op` with the app state on a blocking worker thread, for commands
+/// that mix file system and database work.
+pub(crate) async fn run_blocking<T, F>(app: &AppHandle, op: F) -> Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce(&AppHandle, &AppState) -> Result<T> + Send + 'static,
+{
+    let app = app.clone();
+    tauri::async_runtime::spawn_blocking(move || op(&app, &app.state::<AppState>()))
+        .await
+        .map_err(|e| crate::error::AppError::Unknown(e.to_string()))
+        .and_then(|result| result)
 }
 
 #[tauri::command]
-pub async fn undo_last_action(state: State<'_, AppState>) -> Result<UndoResult> {
+pub async fn undo_last_action(app: AppHandle, state: State<'_, AppState>) -> Result<UndoResult> {
+    let result = run_blocking(&app, undo_last).await;
+    log_failure(&state, "UNDO", None, None, result).await
+}
+
+fn undo_last(app: &AppHandle, state: &AppState) -> Result<UndoResult> {
     let last_actions = state.db.get_last_reversible_action()?;
-    
+
     if last_actions.is_empty() {
         return Err(crate::error::AppError::UndoFailed(
             "No action to undo".to_string()
         ));
     }
 
-    let first_action = &last_actions[0];
-    let action_type = &first_action.action;
-    
-    let result = match action_type.as_str() {
-        "DELETE" => {
-            // Restore from trash
-            let trash_folder = state.trash_manager.get_latest_trash_folder()
-                .ok_or_else(|| crate::error::AppError::UndoFailed(
-                    "No trash folder found".to_string()
-                ))?;
-            
-            let paths: Vec<String> = last_actions
-                .iter()
-                .filter_map(|a| a.src_path.clone())
-                .collect();
-            
-            state.trash_manager.restore_from_trash(&trash_folder, paths)?
-        }
-        "RENAME" => {
-            // Reverse the rename
-            if let (Some(src), Some(dst)) = (&first_action.src_path, &first_action.dst_path) {
-                let src_path = PathBuf::from(dst);
-                let dst_path = PathBuf::from(src);
-                let file_name = dst_path.file_name()
-                    .ok_or_else(|| crate::error::AppError::InvalidPath("Invalid file name".to_string()))?
-                    .to_string_lossy()
-                    .to_string();
-                
-                operations::rename_item(&src_path, &file_name)?;
-                UndoResult {
-                    success: true,
-                    action: action_type.clone(),
-                    items_restored: 1,
-                    message: None,
-                }
-            } else {
-                return Err(crate::error::AppError::UndoFailed(
-                    "Missing path information".to_string()
-                ));
-            }
-        }
-        "MOVE" => {
-            // Reverse the move operation
-            let mut restored = 0;
-            for action in &last_actions {
-                if let (Some(src), Some(dst)) = (&action.src_path, &action.dst_path) {
-                    let src_path = PathBuf::from(src);
-                    let file_name = src_path.file_name()
-                        .ok_or_else(|| crate::error::AppError::InvalidPath("Invalid file name".to_string()))?;
-                    
-                    let current_path = PathBuf::from(dst).join(file_name);
-                    let original_parent = src_path.parent()
-                        .ok_or_else(|| crate::error::AppError::InvalidPath("Invalid parent path".to_string()))?;
-                    
-                    operations::move_items(vec![current_path], original_parent)?;
-                    restored += 1;
-                }
-            }
-            UndoResult {
-                success: true,
-                action: action_type.clone(),
-                items_restored: restored,
-                message: None,
-            }
-        }
-        "CREATE_DIR" => {
-            // Delete the created directory
-            if let Some(path) = &first_action.dst_path {
-                let dir_path = PathBuf::from(path);
-                std::fs::remove_dir(&dir_path)?;
-                UndoResult {
-                    success: true,
-                    action: action_type.clone(),
-                    items_restored: 1,
-                    message: None,
-                }
-            } else {
-                return Err(crate::error::AppError::UndoFailed(
-                    "Missing path information".to_string()
-                ));
-            }
-        }
-        _ => {
-            return Err(crate::error::AppError::UndoFailed(
-                format!("Cannot undo action: {}", action_type)
-            ));
-        }
-    };
+    let result = apply_history(&last_actions, true, state)?;
+    refresh_index_in_background(app, history_paths(&last_actions));
 
-    // Log the undo operation
+    // Log the undo operation, linked to the batch it reversed
     let _ = state.db.log_action(ActionLog {
         id: None,
         timestamp: Utc::now().to_rfc3339(),
@@ -311,32 +435,378 @@ pub async fn undo_last_action(state: State<'_, AppState>) -> Result<UndoResult>
         dst_path: None,
         status: "SUCCESS".to_string(),
         message: Some(format!("Undid {} action(s)", result.items_restored)),
-        batch_id: None,
+        batch_id: last_actions[0].batch_id.clone(),
+        undone: false,
+        origin: LogOrigin::Backend,
+        error_code: None,
     });
 
     Ok(result)
 }
 
 #[tauri::command]
-pub async fn search(current_path: String, query: String, state: State<'_, AppState>) -> Result<Vec<FileItem>> {
-    let path = PathBuf::from(&current_path);
-    
-    let roots = state.allowed_roots.lock().unwrap();
-    if !roots.is_empty() && !fs::is_path_safe(&path, &roots) {
-        return Err(crate::error::AppError::NotAllowed(
-            "Access to this directory is not allowed".to_string()
+pub async fn redo_last_action(app: AppHandle, state: State<'_, AppState>) -> Result<UndoResult> {
+    let result = run_blocking(&app, redo_last).await;
+    log_failure(&state, "REDO", None, None, result).await
+}
+
+fn redo_last(app: &AppHandle, state: &AppState) -> Result<UndoResult> {
+    let undone_actions = state.db.get_last_undone_action()?;
+
+    if undone_actions.is_empty() {
+        return Err(crate::error::AppError::UndoFailed(
+            "No action to redo".to_string()
         ));
     }
 
-    let all_items = operations::list_directory(&path)?;
-    let query_lower = query.to_lowercase();
-    
-    let filtered: Vec<FileItem> = all_items
-        .into_iter()
-        .filter(|item| item.name.to_lowercase().contains(&query_lower))
+    let result = apply_history(&undone_actions, false, state)?;
+    refresh_index_in_background(app, history_paths(&undone_actions));
+
+    let _ = state.db.log_action(ActionLog {
+        id: None,
+        timestamp: Utc::now().to_rfc3339(),
+        action: "REDO".to_string(),
+        src_path: None,
+        dst_path: None,
+        status: "SUCCESS".to_string(),
+        message: Some(format!("Redid {} action(s)", result.items_restored)),
+        batch_id: undone_actions[0].batch_id.clone(),
+        undone: false,
+        origin: LogOrigin::Backend,
+        error_code: None,
+    });
+
+    Ok(result)
+}
+
+#[tauri::command]
+pub async fn undo_batch(batch_id: String, dry_run: Option<bool>, app: AppHandle, state: State<'_, AppState>) -> Result<UndoResult> {
+    let dry_run = dry_run.unwrap_or(false);
+    let result = run_blocking(&app, move |app, state| undo_batch_actions(&batch_id, dry_run, app, state)).await;
+    log_failure(&state, "UNDO", None, None, result).await
+}
+
+fn undo_batch_actions(batch_id: &str, dry_run: bool, app: &AppHandle, state: &AppState) -> Result<UndoResult> {
+    let actions = state.db.get_batch_actions(batch_id)?;
+
+    if actions.is_empty() {
+        return Err(crate::error::AppError::UndoFailed(
+            format!("No reversible actions in batch {}", batch_id)
+        ));
+    }
+
+    let (pending, already_undone): (Vec<ActionLog>, Vec<ActionLog>) =
+        actions.into_iter().partition(|a| !a.undone);
+
+    if pending.is_empty() {
+        return Err(crate::error::AppError::UndoFailed(
+            "Batch has already been undone".to_string()
+        ));
+    }
+
+    let mut result = if dry_run {
+        // Report what would happen without touching anything
+        let sandbox = state.sandbox();
+        let items: Vec<UndoItemResult> = pending
+            .iter()
+            .rev()
+            .map(|action| match check_undo(action, &sandbox) {
+                Ok(false) => left_trash_result(action),
+                checked => undo_item_result(action, checked.map(|_| ())),
+            })
+            .collect();
+        let reversible = items.iter().filter(|i| i.success).count();
+
+        UndoResult {
+            success: reversible == items.len(),
+            action: pending[0].action.clone(),
+            items_restored: 0,
+            message: Some(format!("{} of {} action(s) can be undone", reversible, items.len())),
+            items,
+        }
+    } else {
+        let result = apply_history(&pending, true, state)?;
+        refresh_index_in_background(app, history_paths(&pending));
+
+        let _ = state.db.log_action(ActionLog {
+            id: None,
+            timestamp: Utc::now().to_rfc3339(),
+            action: "UNDO".to_string(),
+            src_path: None,
+            dst_path: None,
+            status: "SUCCESS".to_string(),
+            message: Some(format!("Undid {} action(s)", result.items_restored)),
+            batch_id: Some(batch_id.to_string()),
+            undone: false,
+            origin: LogOrigin::Backend,
+            error_code: None,
+        });
+
+        result
+    };
+
+    for action in &already_undone {
+        result.items.push(undo_item_result(
+            action,
+            Err(crate::error::AppError::UndoFailed("Already undone".to_string())),
+        ));
+    }
+
+    Ok(result)
+}
+
+/// Every path the rows of a batch refer to.
+fn history_paths(actions: &[ActionLog]) -> Vec<PathBuf> {
+    actions
+        .iter()
+        .flat_map(|a| a.src_path.iter().chain(a.dst_path.iter()))
+        .map(PathBuf::from)
+        .collect()
+}
+
+/// Undoes (newest row first) or redoes (oldest row first) the rows of one
+/// batch and flips their undone flag. Rows that fail are left as they were,
+/// and every row gets an entry in the returned report.
+fn apply_history(actions: &[ActionLog], undo: bool, state: &AppState) -> Result<UndoResult> {
+    let action_type = actions[0].action.clone();
+
+    let mut ordered: Vec<&ActionLog> = actions.iter().collect();
+    if undo {
+        ordered.reverse();
+    }
+
+    let sandbox = state.sandbox();
+    let mut applied = Vec::new();
+    let mut items = Vec::new();
+
+    for action in ordered {
+        let outcome = if undo {
+            match check_undo(action, &sandbox) {
+                // Left as it is, without failing the rest of the batch
+                Ok(false) => {
+                    items.push(left_trash_result(action));
+                    continue;
+                }
+                checked => checked.and_then(|_| undo_entry(action, &sandbox, state)),
+            }
+        } else {
+            redo_entry(action, &sandbox, state)
+        };
+
+        match &outcome {
+            Ok(_) => applied.extend(action.id),
+            Err(e) => {
+                let _ = state.db.log_action(ActionLog {
+                    id: None,
+                    timestamp: Utc::now().to_rfc3339(),
+                    action: if undo { "UNDO" } else { "REDO" }.to_string(),
+                    src_path: action.src_path.clone(),
+                    dst_path: action.dst_path.clone(),
+                    status: "ERROR".to_string(),
+                    message: Some(e.to_string()),
+                    batch_id: action.batch_id.clone(),
+                    undone: false,
+                    origin: LogOrigin::Backend,
+                    error_code: Some(e.error_code().to_string()),
+                });
+            }
+        }
+        items.push(undo_item_result(action, outcome));
+    }
+
+    let errors: Vec<String> = items
+        .iter()
+        .filter(|i| !i.success)
+        .filter_map(|i| i.message.clone())
         .collect();
 
-    Ok(filtered)
+    if applied.is_empty() {
+        let message = if errors.is_empty() {
+            "Every item already left the trash".to_string()
+        } else {
+            errors.join("; ")
+        };
+        return Err(crate::error::AppError::UndoFailed(message));
+    }
+
+    state.db.set_undone(&applied, undo)?;
+
+    Ok(UndoResult {
+        success: errors.is_empty(),
+        action: action_type,
+        items_restored: applied.len(),
+        message: if errors.is_empty() { None } else { Some(errors.join("; ")) },
+        items,
+    })
+}
+
+fn undo_item_result(action: &ActionLog, outcome: Result<()>) -> UndoItemResult {
+    let (success, message) = match outcome {
+        Ok(_) => (true, None),
+        Err(e) => (false, Some(e.to_string())),
+    };
+
+    UndoItemResult {
+        log_id: action.id,
+        action: action.action.clone(),
+        path: action.src_path.clone().or_else(|| action.dst_path.clone()),
+        success,
+        message,
+    }
+}
+
+/// Report entry of a delete that has nothing left to undo.
+fn left_trash_result(action: &ActionLog) -> UndoItemResult {
+    UndoItemResult {
+        log_id: action.id,
+        action: action.action.clone(),
+        path: action.src_path.clone(),
+        success: true,
+        message: Some("Already left the trash".to_string()),
+    }
+}
+
+/// Checks that everything a logged action touched is still where the log
+/// says it is, so reversing it can't clobber anything that changed since.
+/// `false` for a delete whose item has since been restored or purged from
+/// the trash, which is left out of the undo.
+fn check_undo(action: &ActionLog, sandbox: &Sandbox) -> Result<bool> {
+    let src = action.src_path.as_ref().map(PathBuf::from);
+    let dst = action.dst_path.as_ref().map(PathBuf::from);
+
+    let expect_present = |path: &std::path::Path, what: &str| {
+        let location = sandbox.locate(path)?;
+        if !location.exists() {
+            return Err(crate::error::AppError::UndoFailed(
+                format!("{} {} no longer exists", what, path.display())
+            ));
+        }
+        Ok(location)
+    };
+
+    match (action.action.as_str(), src, dst) {
+        ("MOVE" | "RENAME", Some(src), Some(dst)) => {
+            expect_present(&dst, "Moved item")?;
+            ensure_vacant(&sandbox.locate(&src)?).map(|_| true)
+        }
+        ("COPY", _, Some(dst)) => expect_present(&dst, "Copy").map(|_| true),
+        ("CREATE_DIR", _, Some(dst)) => {
+            let is_empty = expect_present(&dst, "Directory")?
+                .read_dir()
+                .map(|mut entries| entries.next().is_none())
+                .unwrap_or(false);
+            if !is_empty {
+                return Err(crate::error::AppError::UndoFailed(
+                    format!("Directory {} is no longer empty", dst.display())
+                ));
+            }
+            Ok(true)
+        }
+        ("DELETE", Some(src), Some(dst)) => {
+            if !Location::ambient(&dst).is_ok_and(|slot| slot.exists()) {
+                return Ok(false);
+            }
+            ensure_vacant(&sandbox.locate(&src)?).map(|_| true)
+        }
+        // Legacy deletes without a recorded trash path are checked while restoring
+        _ => Ok(true),
+    }
+}
+
+/// Reverses a single logged action.
+fn undo_entry(action: &ActionLog, sandbox: &Sandbox, state: &AppState) -> Result<()> {
+    let src = action.src_path.as_ref().map(PathBuf::from);
+    let dst = action.dst_path.as_ref().map(PathBuf::from);
+
+    match (action.action.as_str(), src, dst) {
+        ("MOVE", Some(src), Some(dst)) => {
+            let src = sandbox.locate(&src)?;
+            ensure_vacant(&src)?;
+            operations::move_entry(&sandbox.locate(&dst)?, &src, &NoProgress)
+        }
+        // Remove the copy
+        ("COPY", _, Some(dst)) => sandbox.locate(&dst)?.remove(),
+        ("RENAME", Some(src), Some(dst)) => {
+            let file_name = src.file_name()
+                .ok_or_else(|| crate::error::AppError::InvalidPath("Invalid file name".to_string()))?
+                .to_string_lossy()
+                .to_string();
+            operations::rename_item(&sandbox.locate(&dst)?, &file_name).map(|_| ())
+        }
+        // Delete the created directory
+        ("CREATE_DIR", _, Some(dst)) => sandbox.locate(&dst)?.remove_dir(),
+        ("DELETE", Some(src), dst) => {
+            let trash_path = trash_path_of(action, &src, dst, state)?;
+            state.trash_manager.restore_item(&trash_path, &sandbox.locate(&src)?)
+        }
+        ("MOVE" | "COPY" | "RENAME" | "CREATE_DIR" | "DELETE", _, _) => Err(crate::error::AppError::UndoFailed(
+            "Missing path information".to_string()
+        )),
+        (other, _, _) => Err(crate::error::AppError::UndoFailed(
+            format!("Cannot undo action: {}", other)
+        )),
+    }
+}
+
+/// Applies an undone action again, exactly as it was logged.
+fn redo_entry(action: &ActionLog, sandbox: &Sandbox, state: &AppState) -> Result<()> {
+    let src = action.src_path.as_ref().map(PathBuf::from);
+    let dst = action.dst_path.as_ref().map(PathBuf::from);
+
+    match (action.action.as_str(), src, dst) {
+        ("MOVE", Some(src), Some(dst)) => {
+            let dst = sandbox.locate(&dst)?;
+            ensure_vacant(&dst)?;
+            operations::move_entry(&sandbox.locate(&src)?, &dst, &NoProgress)
+        }
+        ("COPY", Some(src), Some(dst)) => {
+            let dst = sandbox.locate(&dst)?;
+            ensure_vacant(&dst)?;
+            operations::copy_entry(&sandbox.locate(&src)?, &dst, SymlinkPolicy::Preserve, &NoProgress)
+        }
+        ("RENAME", Some(src), Some(dst)) => {
+            let file_name = dst.file_name()
+                .ok_or_else(|| crate::error::AppError::InvalidPath("Invalid file name".to_string()))?
+                .to_string_lossy()
+                .to_string();
+            operations::rename_item(&sandbox.locate(&src)?, &file_name).map(|_| ())
+        }
+        ("CREATE_DIR", _, Some(dst)) => sandbox.locate(&dst)?.create_dir(),
+        // Back into the same trash slot it was restored from
+        ("DELETE", Some(src), dst) => {
+            let trash_path = trash_path_of(action, &src, dst, state)?;
+            let batch_id = action.batch_id.as_deref().unwrap_or_default();
+            state.trash_manager.retrash_item(&sandbox.locate(&src)?, &trash_path, batch_id)
+        }
+        ("MOVE" | "COPY" | "RENAME" | "CREATE_DIR" | "DELETE", _, _) => Err(crate::error::AppError::UndoFailed(
+            "Missing path information".to_string()
+        )),
+        (other, _, _) => Err(crate::error::AppError::UndoFailed(
+            format!("Cannot redo action: {}", other)
+        )),
+    }
+}
+
+/// Trash slot of a logged DELETE. Rows that predate recorded trash paths
+/// are resolved through the batch metadata, which outlives restores.
+fn trash_path_of(action: &ActionLog, src: &std::path::Path, dst: Option<PathBuf>, state: &AppState) -> Result<PathBuf> {
+    match (dst, &action.batch_id) {
+        (Some(dst), _) => Ok(dst),
+        (None, Some(batch_id)) => state.trash_manager.find_trash_path(batch_id, src)
+            .ok_or_else(|| crate::error::AppError::UndoFailed(
+                format!("No trash record found for {}", src.display())
+            )),
+        (None, None) => Err(crate::error::AppError::UndoFailed(
+            "Missing trash location".to_string()
+        )),
+    }
+}
+
+fn ensure_vacant(location: &Location) -> Result<()> {
+    if location.exists() {
+        return Err(crate::error::AppError::FileExists(location.path().to_string_lossy().to_string()));
+    }
+    Ok(())
 }
 
 #[tauri::command]
@@ -363,8 +833,25 @@ pub async fn get_favorites() -> Result<Vec<String>> {
 }
 
 #[tauri::command]
-pub async fn set_allowed_roots(roots: Vec<String>, state: State<'_, AppState>) -> Result<()> {
+pub async fn get_allowed_roots(state: State<'_, AppState>) -> Result<RootSettings> {
+    Ok(state.allowed_roots.lock().unwrap().clone())
+}
+
+/// Replaces the allowed roots and stores them for the next start. `strict`
+/// is left as it was when not given.
+#[tauri::command]
+pub async fn set_allowed_roots(roots: Vec<AllowedRoot>, strict: Option<bool>, state: State<'_, AppState>) -> Result<()> {
+    let strict = strict.unwrap_or_else(|| state.allowed_roots.lock().unwrap().strict);
+    let stored = roots.clone();
+    let result = state.db.run(move |db| {
+        db.set_allowed_roots(&stored)?;
+        db.set_setting(STRICT_ROOTS_KEY, &strict)
+    }).await;
+    log_failure(&state, "SET_ALLOWED_ROOTS", None, None, result).await?;
+
+    let sandbox = Arc::new(Sandbox::new(&roots, strict));
     let mut allowed_roots = state.allowed_roots.lock().unwrap();
-    *allowed_roots = roots.into_iter().map(PathBuf::from).collect();
+    *state.sandbox.lock().unwrap() = sandbox;
+    *allowed_roots = RootSettings { roots, strict };
     Ok(())
 }
\ No newline at end of file
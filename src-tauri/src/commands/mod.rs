@@ -1,5 +1,11 @@
 pub mod file_ops;
 pub mod db_ops;
+pub mod trash_ops;
+pub mod search_ops;
+pub mod watch_ops;
 
 pub use file_ops::*;
-pub use db_ops::*;
\ No newline at end of file
+pub use db_ops::*;
+pub use trash_ops::*;
+pub use search_ops::*;
+pub use watch_ops::*;
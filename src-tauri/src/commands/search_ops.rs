@@ -0,0 +1,153 @@
+use crate::commands::file_ops::AppState;
+use crate::db::{IndexFilters, IndexRootStatus};
+use crate::error::{AppError, Result};
+use crate::fs::FileItem;
+use crate::fs::content_search::{ContentSearchOptions, ContentSearchResult, ContentSearcher};
+use crate::fs::operations::NoProgress;
+use crate::fs::search::{SearchFilters, Searcher};
+use crate::index;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager, State};
+
+/// Event carrying a `SearchChunk` of matches for a running search.
+pub const SEARCH_RESULTS_EVENT: &str = "search-results";
+
+/// How often indexed roots are re-crawled to pick up outside changes.
+const INDEX_REFRESH_INTERVAL: Duration = Duration::from_secs(30 * 60);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchChunk {
+    pub job_id: String,
+    pub items: Vec<FileItem>,
+    /// Set on the last chunk of a search, together with the total
+    pub done: bool,
+    pub total: Option<usize>,
+}
+
+/// Starts a recursive search under `current_path` and returns its job id
+/// right away. Matches arrive as `search-results` events, which may come
+/// before the command returns; callers that pass their own `job_id` can
+/// tell their chunks apart from the first one. The search can be stopped
+/// with `cancel_job`.
+#[tauri::command]
+pub async fn search(current_path: String, query: String, filters: Option<SearchFilters>, job_id: Option<String>, app: AppHandle, state: State<'_, AppState>) -> Result<String> {
+    // A symlinked directory is searched where it leads, if that is inside the roots
+    let root = state.sandbox().locate(Path::new(&current_path))?.follow()?;
+
+    let searcher = Searcher::new(&query, filters.unwrap_or_default())?;
+    let job = state.jobs.start("SEARCH", job_id, &app)?;
+    let job_id = job.id();
+
+    let id = job_id.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let chunk = |items, total: Option<usize>| SearchChunk { job_id: id.clone(), items, done: total.is_some(), total };
+
+        let found = searcher.search(&root, &job, &mut |items| {
+            let _ = app.emit(SEARCH_RESULTS_EVENT, chunk(items, None));
+        });
+
+        let _ = app.emit(SEARCH_RESULTS_EVENT, chunk(Vec::new(), Some(found)));
+        job.finish(Ok(None));
+    });
+
+    Ok(job_id)
+}
+
+/// Finds lines containing `query` in the text files under `current_path`.
+/// Runs as a job, so it shows up in `list_jobs` and can be cancelled.
+#[tauri::command]
+pub async fn search_contents(current_path: String, query: String, options: Option<ContentSearchOptions>, app: AppHandle, state: State<'_, AppState>) -> Result<ContentSearchResult> {
+    let root = state.sandbox().locate(Path::new(&current_path))?.follow()?;
+
+    let searcher = ContentSearcher::new(&query, options.unwrap_or_default())?;
+    let job = state.jobs.start("SEARCH_CONTENTS", None, &app)?;
+    let worker = job.clone();
+
+    let result = tauri::async_runtime::spawn_blocking(move || searcher.search(&root, &worker))
+        .await
+        .map_err(|e| AppError::Unknown(e.to_string()));
+
+    job.finish(result.as_ref().map(|_| None).map_err(|e| e.to_string()));
+    result
+}
+
+/// Indexed roots that are still inside the allowed roots.
+#[tauri::command]
+pub async fn index_status(state: State<'_, AppState>) -> Result<Vec<IndexRootStatus>> {
+    let sandbox = state.sandbox();
+    let roots = state.db.run(|db| db.get_index_roots()).await?;
+    Ok(roots.into_iter().filter(|status| sandbox.contains(Path::new(&status.root))).collect())
+}
+
+/// Crawls `root` into the filename index. Roots indexed once are kept
+/// current from then on.
+#[tauri::command]
+pub async fn reindex_root(root: String, app: AppHandle, state: State<'_, AppState>) -> Result<IndexRootStatus> {
+    // A symlinked root is indexed where it leads, if that is inside the roots
+    let root = state.sandbox().locate(Path::new(&root))?.follow()?;
+
+    let job = state.jobs.start("INDEX", None, &app)?;
+    let worker = job.clone();
+    let handle = app.clone();
+
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        let state = handle.state::<AppState>();
+        state.indexer.index_root(&state.db, &root, &worker)
+    })
+    .await
+    .map_err(|e| AppError::Unknown(e.to_string()))
+    .and_then(|result| result);
+
+    job.finish(result.as_ref().map(|_| None).map_err(|e| e.to_string()));
+    result
+}
+
+/// Searches the filename index. Entries outside the allowed roots, or gone
+/// from disk since they were indexed, are left out.
+#[tauri::command]
+pub async fn search_index(query: String, filters: Option<IndexFilters>, state: State<'_, AppState>) -> Result<Vec<FileItem>> {
+    let sandbox = state.sandbox();
+    let filters = filters.unwrap_or_default();
+    let entries = state.db.run(move |db| db.search_index(&query, &filters)).await?;
+
+    Ok(entries
+        .into_iter()
+        .filter(|entry| {
+            sandbox.locate(Path::new(&entry.path)).is_ok_and(|location| location.exists())
+        })
+        .map(index::to_file_item)
+        .collect())
+}
+
+/// Re-crawls every indexed root now and then for as long as the app runs.
+pub fn spawn_index_refresher(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        let mut interval = tokio::time::interval(INDEX_REFRESH_INTERVAL);
+        loop {
+            interval.tick().await;
+            let handle = app.clone();
+            let _ = tauri::async_runtime::spawn_blocking(move || {
+                let state = handle.state::<AppState>();
+                let sandbox = state.sandbox();
+                for status in state.db.get_index_roots().unwrap_or_default() {
+                    if let Ok(root) = sandbox.locate(Path::new(&status.root)) {
+                        let _ = state.indexer.index_root(&state.db, &root, &NoProgress);
+                    }
+                }
+            })
+            .await;
+        }
+    });
+}
+
+/// Updates the index for paths an operation created, changed or removed,
+/// on a blocking worker without waiting for it.
+pub fn refresh_index_in_background(app: &AppHandle, paths: Vec<PathBuf>) {
+    let app = app.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let state = app.state::<AppState>();
+        let _ = state.indexer.refresh_paths(&state.db, &state.sandbox(), &paths);
+    });
+}
@@ -0,0 +1,213 @@
+use crate::commands::file_ops::{run_blocking, AppState};
+use crate::commands::search_ops::refresh_index_in_background;
+use crate::db::{ActionLog, LogOrigin};
+use crate::error::Result;
+use crate::fs::conflict::{ConflictPolicy, ConflictResolution};
+use crate::fs::operations::{BatchItemResult, BatchResult};
+use crate::fs::trash::{TrashBackendKind, TrashItem, TrashRetention, TrashUsage};
+use chrono::Utc;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tauri::{AppHandle, Manager, State};
+use uuid::Uuid;
+
+const TRASH_RETENTION_KEY: &str = "trash_retention";
+
+pub const TRASH_BACKEND_KEY: &str = "trash_backend";
+
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+#[tauri::command]
+pub async fn list_trash(app: AppHandle) -> Result<Vec<TrashItem>> {
+    run_blocking(&app, |_, state| state.trash_manager.list_items()).await
+}
+
+#[tauri::command]
+pub async fn restore_trash_items(ids: Vec<String>, conflict_policy: Option<ConflictPolicy>, app: AppHandle) -> Result<BatchResult> {
+    let policy = conflict_policy.unwrap_or_default();
+    run_blocking(&app, move |app, state| restore_items(ids, policy, app, state)).await
+}
+
+fn restore_items(ids: Vec<String>, policy: ConflictPolicy, app: &AppHandle, state: &AppState) -> Result<BatchResult> {
+    let batch_id = Uuid::new_v4().to_string();
+    let sandbox = state.sandbox();
+    let mut results = Vec::new();
+
+    for id in ids {
+        let item = match state.trash_manager.get_item(&id) {
+            Ok(item) => item,
+            Err(e) => {
+                results.push(BatchItemResult::failed(Path::new(&id), e));
+                continue;
+            }
+        };
+
+        let result = state.trash_manager.restore_trash_item(&sandbox, &item, policy);
+
+        if result.was_applied() {
+            let _ = state.db.log_action(ActionLog {
+                id: None,
+                timestamp: Utc::now().to_rfc3339(),
+                action: "RESTORE".to_string(),
+                src_path: Some(item.trash_path.clone()),
+                dst_path: result.dest_path.clone(),
+                status: "SUCCESS".to_string(),
+                // The replaced entry stays in the trash, where it can be restored from
+                message: match &result.replaced_path {
+                    Some(replaced) => Some(format!("Conflict resolved: overwritten, replaced entry trashed at {}", replaced)),
+                    None => result.resolution.log_message(),
+                },
+                batch_id: Some(batch_id.clone()),
+                undone: false,
+                origin: LogOrigin::Backend,
+                error_code: None,
+            });
+        }
+
+        results.push(result);
+    }
+
+    let restored = results
+        .iter()
+        .filter(|r| r.was_applied())
+        .filter_map(|r| r.dest_path.as_ref().map(PathBuf::from))
+        .collect();
+    refresh_index_in_background(app, restored);
+
+    Ok(BatchResult::from_results(batch_id, results))
+}
+
+#[tauri::command]
+pub async fn purge_trash_items(ids: Vec<String>, app: AppHandle) -> Result<BatchResult> {
+    run_blocking(&app, move |_, state| {
+        let batch_id = Uuid::new_v4().to_string();
+        let mut results = Vec::new();
+
+        for id in ids {
+            let result = match state.trash_manager.get_item(&id) {
+                Ok(item) => purge_item(&item, &batch_id, None, state),
+                Err(e) => BatchItemResult::failed(Path::new(&id), e),
+            };
+            results.push(result);
+        }
+
+        Ok(BatchResult::from_results(batch_id, results))
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn empty_trash(app: AppHandle) -> Result<BatchResult> {
+    run_blocking(&app, |_, state| {
+        let batch_id = Uuid::new_v4().to_string();
+
+        let results = state
+            .trash_manager
+            .list_items()?
+            .iter()
+            .map(|item| purge_item(item, &batch_id, None, state))
+            .collect();
+
+        Ok(BatchResult::from_results(batch_id, results))
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn trash_usage(app: AppHandle) -> Result<TrashUsage> {
+    run_blocking(&app, |_, state| state.trash_manager.usage()).await
+}
+
+#[tauri::command]
+pub async fn get_trash_retention(state: State<'_, AppState>) -> Result<TrashRetention> {
+    Ok(state.db.run(|db| db.get_setting(TRASH_RETENTION_KEY)).await?.unwrap_or_default())
+}
+
+#[tauri::command]
+pub async fn set_trash_retention(retention: TrashRetention, app: AppHandle, state: State<'_, AppState>) -> Result<()> {
+    state.db.run(move |db| db.set_setting(TRASH_RETENTION_KEY, &retention)).await?;
+    sweep_in_background(&app);
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn get_trash_backend(state: State<'_, AppState>) -> Result<TrashBackendKind> {
+    Ok(state.trash_manager.backend())
+}
+
+/// Switches the trash new deletions go to. Items already trashed stay
+/// where they are and can still be restored and purged.
+#[tauri::command]
+pub async fn set_trash_backend(backend: TrashBackendKind, state: State<'_, AppState>) -> Result<()> {
+    state.trash_manager.set_backend(backend)?;
+    state.db.run(move |db| db.set_setting(TRASH_BACKEND_KEY, &backend)).await
+}
+
+/// Periodically enforces the trash retention settings for as long as the app runs.
+pub fn spawn_trash_sweeper(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        let mut interval = tokio::time::interval(SWEEP_INTERVAL);
+        loop {
+            interval.tick().await;
+            sweep_in_background(&app);
+        }
+    });
+}
+
+/// Runs a sweep on a blocking worker without waiting for it.
+pub fn sweep_in_background(app: &AppHandle) {
+    let app = app.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let state = app.state::<AppState>();
+        let _ = sweep_trash(&state);
+    });
+}
+
+/// Purges whole batches, oldest first, until the trash is within the
+/// configured retention limits. Every purged item is logged.
+fn sweep_trash(state: &AppState) -> Result<BatchResult> {
+    let batch_id = Uuid::new_v4().to_string();
+    let retention: TrashRetention = state.db.get_setting(TRASH_RETENTION_KEY)?.unwrap_or_default();
+
+    let results = retention
+        .select_expired(state.trash_manager.list_items()?)
+        .into_iter()
+        .map(|(item, reason)| purge_item(&item, &batch_id, Some(reason), state))
+        .collect();
+
+    Ok(BatchResult::from_results(batch_id, results))
+}
+
+/// Permanently deletes one trash item and logs it as a PURGE. Only sweeps
+/// pass a `reason`, which also keeps their purges from ending the redo history.
+fn purge_item(item: &TrashItem, batch_id: &str, reason: Option<String>, state: &AppState) -> BatchItemResult {
+    let trash_path = Path::new(&item.trash_path);
+
+    if let Err(e) = state.trash_manager.purge_item(item) {
+        return BatchItemResult::failed(trash_path, e);
+    }
+
+    let _ = state.db.log_action(ActionLog {
+        id: None,
+        timestamp: Utc::now().to_rfc3339(),
+        action: "PURGE".to_string(),
+        src_path: Some(item.original_path.clone()),
+        dst_path: Some(item.trash_path.clone()),
+        status: "SUCCESS".to_string(),
+        message: reason,
+        batch_id: Some(batch_id.to_string()),
+        undone: false,
+        origin: LogOrigin::Backend,
+        error_code: None,
+    });
+
+    BatchItemResult {
+        path: item.trash_path.clone(),
+        success: true,
+        message: None,
+        dest_path: None,
+        resolution: ConflictResolution::NoConflict,
+        error_code: None,
+        replaced_path: None,
+    }
+}
@@ -0,0 +1,20 @@
+use crate::commands::file_ops::AppState;
+use crate::error::Result;
+use std::path::PathBuf;
+use tauri::{AppHandle, State};
+
+/// Starts sending `dir-change` events for the direct children of `path`.
+/// Every call must be paired with an `unwatch_dir`.
+#[tauri::command]
+pub async fn watch_dir(path: String, app: AppHandle, state: State<'_, AppState>) -> Result<()> {
+    let path = PathBuf::from(&path);
+
+    state.sandbox().locate(&path)?;
+
+    state.watcher.watch(&path, &app)
+}
+
+#[tauri::command]
+pub async fn unwatch_dir(path: String, state: State<'_, AppState>) -> Result<()> {
+    state.watcher.unwatch(&PathBuf::from(&path))
+}
@@ -0,0 +1,320 @@
+use super::{escape_like, Database};
+use crate::error::{AppError, Result};
+use crate::fs::search::EntryKind;
+use chrono::DateTime;
+use rusqlite::types::ToSql;
+use rusqlite::{params, TransactionBehavior};
+use serde::{Deserialize, Serialize};
+
+/// Results returned by `search_index` unless the caller sets its own limit.
+const DEFAULT_LIMIT: usize = 500;
+
+/// Names are matched through a trigram FTS5 table, which needs at least
+/// three characters per term; shorter terms fall back to LIKE.
+const MIN_FTS_TERM: usize = 3;
+
+/// One file or directory as stored in the index.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexEntry {
+    pub path: String,
+    pub name: String,
+    pub ext: Option<String>,
+    pub size: u64,
+    /// Milliseconds since the Unix epoch
+    pub mtime: i64,
+    pub is_dir: bool,
+    pub is_symlink: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IndexState {
+    Idle,
+    Indexing,
+    Failed,
+}
+
+impl IndexState {
+    fn as_str(&self) -> &'static str {
+        match self {
+            IndexState::Idle => "idle",
+            IndexState::Indexing => "indexing",
+            IndexState::Failed => "failed",
+        }
+    }
+
+    fn parse(value: &str) -> Self {
+        match value {
+            "indexing" => IndexState::Indexing,
+            "failed" => IndexState::Failed,
+            _ => IndexState::Idle,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexRootStatus {
+    pub root: String,
+    pub state: IndexState,
+    pub entry_count: usize,
+    pub last_indexed_at: Option<String>,
+    pub message: Option<String>,
+}
+
+/// Optional narrowing of an index search. Everything left out matches.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IndexFilters {
+    /// Only entries below this directory
+    pub under: Option<String>,
+    /// Extensions without the dot, compared case-insensitively
+    pub extensions: Vec<String>,
+    pub min_size: Option<u64>,
+    pub max_size: Option<u64>,
+    /// RFC 3339 timestamps
+    pub modified_after: Option<String>,
+    pub modified_before: Option<String>,
+    pub kind: Option<EntryKind>,
+    pub limit: Option<usize>,
+}
+
+fn parse_millis(value: &str) -> Result<i64> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|t| t.timestamp_millis())
+        .map_err(|e| AppError::InvalidQuery(format!("{}: {}", value, e)))
+}
+
+impl Database {
+    /// Adds or refreshes entries of `root`, stamping them with crawl `seen`.
+    pub fn index_entries(&self, root: &str, entries: &[IndexEntry], seen: i64) -> Result<()> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        {
+            let mut stmt = tx.prepare_cached(
+                "INSERT INTO file_entries (path, root, name, ext, size, mtime, is_dir, is_symlink, seen)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+                 ON CONFLICT(path) DO UPDATE SET
+                    root = excluded.root, size = excluded.size, mtime = excluded.mtime,
+                    is_dir = excluded.is_dir, is_symlink = excluded.is_symlink, seen = excluded.seen",
+            )?;
+            for entry in entries {
+                stmt.execute(params![
+                    entry.path,
+                    root,
+                    entry.name,
+                    entry.ext,
+                    entry.size as i64,
+                    entry.mtime,
+                    entry.is_dir,
+                    entry.is_symlink,
+                    seen
+                ])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Drops entries of `root` that the crawl stamped `seen` didn't find.
+    pub fn remove_unseen_entries(&self, root: &str, seen: i64) -> Result<usize> {
+        let conn = self.conn()?;
+        let removed = conn.execute(
+            "DELETE FROM file_entries WHERE root = ?1 AND seen < ?2",
+            params![root, seen],
+        )?;
+        Ok(removed)
+    }
+
+    /// Drops `path` and everything indexed below it.
+    pub fn remove_index_path(&self, path: &str) -> Result<()> {
+        let conn = self.conn()?;
+        conn.execute(
+            "DELETE FROM file_entries WHERE path = ?1 OR path LIKE ?2 ESCAPE '\\'",
+            params![path, format!("{}/%", escape_like(path.trim_end_matches('/')))],
+        )?;
+        Ok(())
+    }
+
+    /// Records the state of a root. `entry_count` is always counted live.
+    pub fn set_index_root(&self, status: &IndexRootStatus) -> Result<()> {
+        let conn = self.conn()?;
+        conn.execute(
+            "INSERT INTO index_roots (root, state, last_indexed_at, message)
+             VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT(root) DO UPDATE SET
+                state = excluded.state, last_indexed_at = excluded.last_indexed_at,
+                message = excluded.message",
+            params![status.root, status.state.as_str(), status.last_indexed_at, status.message],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_index_root(&self, root: &str) -> Result<Option<IndexRootStatus>> {
+        Ok(self.get_index_roots()?.into_iter().find(|status| status.root == root))
+    }
+
+    pub fn get_index_roots(&self) -> Result<Vec<IndexRootStatus>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT root, state, last_indexed_at, message,
+                    (SELECT COUNT(*) FROM file_entries e WHERE e.root = r.root)
+             FROM index_roots r
+             ORDER BY root",
+        )?;
+
+        let roots = stmt
+            .query_map([], |row| {
+                Ok(IndexRootStatus {
+                    root: row.get(0)?,
+                    state: IndexState::parse(&row.get::<_, String>(1)?),
+                    last_indexed_at: row.get(2)?,
+                    message: row.get(3)?,
+                    entry_count: row.get::<_, i64>(4)? as usize,
+                })
+            })?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        Ok(roots)
+    }
+
+    /// Entries whose name contains every whitespace-separated term of
+    /// `query`, case-insensitively, shortest names first.
+    pub fn search_index(&self, query: &str, filters: &IndexFilters) -> Result<Vec<IndexEntry>> {
+        let mut conditions: Vec<String> = Vec::new();
+        let mut values: Vec<Box<dyn ToSql>> = Vec::new();
+
+        let (long, short): (Vec<&str>, Vec<&str>) = query
+            .split_whitespace()
+            .partition(|term| term.chars().count() >= MIN_FTS_TERM);
+
+        if !long.is_empty() {
+            let expression = long
+                .iter()
+                .map(|term| format!("\"{}\"", term.replace('"', "\"\"")))
+                .collect::<Vec<_>>()
+                .join(" AND ");
+            conditions.push("e.id IN (SELECT rowid FROM file_index WHERE file_index MATCH ?)".to_string());
+            values.push(Box::new(expression));
+        }
+        for term in short {
+            conditions.push("e.name LIKE ? ESCAPE '\\'".to_string());
+            values.push(Box::new(format!("%{}%", escape_like(term))));
+        }
+
+        if let Some(under) = &filters.under {
+            conditions.push("e.path LIKE ? ESCAPE '\\'".to_string());
+            values.push(Box::new(format!("{}/%", escape_like(under.trim_end_matches('/')))));
+        }
+        if !filters.extensions.is_empty() {
+            let placeholders = vec!["?"; filters.extensions.len()].join(", ");
+            conditions.push(format!("lower(e.ext) IN ({})", placeholders));
+            for ext in &filters.extensions {
+                values.push(Box::new(ext.trim_start_matches('.').to_lowercase()));
+            }
+        }
+        // Directory sizes aren't meaningful here, so size limits only apply to files
+        if let Some(min) = filters.min_size {
+            conditions.push("(e.is_dir = 1 OR e.size >= ?)".to_string());
+            values.push(Box::new(min as i64));
+        }
+        if let Some(max) = filters.max_size {
+            conditions.push("(e.is_dir = 1 OR e.size <= ?)".to_string());
+            values.push(Box::new(max as i64));
+        }
+        if let Some(after) = &filters.modified_after {
+            conditions.push("e.mtime >= ?".to_string());
+            values.push(Box::new(parse_millis(after)?));
+        }
+        if let Some(before) = &filters.modified_before {
+            conditions.push("e.mtime <= ?".to_string());
+            values.push(Box::new(parse_millis(before)?));
+        }
+        match filters.kind {
+            Some(EntryKind::File) => conditions.push("e.is_dir = 0".to_string()),
+            Some(EntryKind::Dir) => conditions.push("e.is_dir = 1".to_string()),
+            None => {}
+        }
+
+        let where_clause = if conditions.is_empty() {
+            String::new()
+        } else {
+            format!("WHERE {}", conditions.join(" AND "))
+        };
+        values.push(Box::new(filters.limit.unwrap_or(DEFAULT_LIMIT) as i64));
+
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT e.path, e.name, e.ext, e.size, e.mtime, e.is_dir, e.is_symlink FROM file_entries e
+             {}
+             ORDER BY length(e.name), e.name
+             LIMIT ?",
+            where_clause
+        ))?;
+
+        let entries = stmt
+            .query_map(rusqlite::params_from_iter(values.iter()), |row| {
+                Ok(IndexEntry {
+                    path: row.get(0)?,
+                    name: row.get(1)?,
+                    ext: row.get(2)?,
+                    size: row.get::<_, i64>(3)? as u64,
+                    mtime: row.get(4)?,
+                    is_dir: row.get(5)?,
+                    is_symlink: row.get(6)?,
+                })
+            })?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(path: &str, size: u64) -> IndexEntry {
+        let name = path.rsplit('/').next().unwrap().to_string();
+        IndexEntry {
+            ext: name.rsplit_once('.').map(|(_, ext)| ext.to_string()),
+            path: path.to_string(),
+            name,
+            size,
+            mtime: 0,
+            is_dir: false,
+            is_symlink: false,
+        }
+    }
+
+    fn names(entries: Vec<IndexEntry>) -> Vec<String> {
+        entries.into_iter().map(|e| e.name).collect()
+    }
+
+    #[test]
+    fn test_index_search_and_refresh() {
+        let db = Database::new(":memory:").unwrap();
+        db.index_entries(
+            "/r",
+            &[entry("/r/Quarterly Report.pdf", 900), entry("/r/a/report.txt", 10), entry("/r/a/x.md", 1)],
+            1,
+        )
+        .unwrap();
+
+        assert_eq!(names(db.search_index("REPORT", &IndexFilters::default()).unwrap()), vec!["report.txt", "Quarterly Report.pdf"]);
+        assert_eq!(names(db.search_index("port qu", &IndexFilters::default()).unwrap()), vec!["Quarterly Report.pdf"]);
+        assert_eq!(names(db.search_index("md", &IndexFilters::default()).unwrap()), vec!["x.md"]);
+
+        let filters = IndexFilters { extensions: vec!["PDF".to_string()], min_size: Some(100), ..Default::default() };
+        assert_eq!(names(db.search_index("", &filters).unwrap()), vec!["Quarterly Report.pdf"]);
+        let under = IndexFilters { under: Some("/r/a".to_string()), ..Default::default() };
+        assert_eq!(db.search_index("", &under).unwrap().len(), 2);
+
+        // A later crawl that no longer finds an entry drops it from the FTS table too
+        db.index_entries("/r", &[entry("/r/a/report.txt", 10)], 2).unwrap();
+        assert_eq!(db.remove_unseen_entries("/r", 2).unwrap(), 2);
+        assert_eq!(names(db.search_index("report", &IndexFilters::default()).unwrap()), vec!["report.txt"]);
+
+        db.remove_index_path("/r/a").unwrap();
+        assert!(db.search_index("report", &IndexFilters::default()).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,223 @@
+//! Hash chain over `action_logs`.
+//!
+//! Every row stores a SHA-256 over its content as logged and the hash of the
+//! row before it, so editing or deleting a row breaks the link of the row
+//! itself or of the one after it. The `undone` flag is left out: undo and
+//! redo flip it on rows that are already sealed. Pruning removes rows from
+//! the start only and keeps the hash the first remaining row chains to; the
+//! prune's own row records that hash too, so the stored copy can't be swapped
+//! for one that hides deleted rows.
+
+use super::retention::{recorded_anchor, CHAIN_ANCHOR_KEY, PRUNE_ACTION};
+use super::{row_to_log, ActionLog, Database, LogOrigin, LOG_COLUMNS};
+use crate::error::Result;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Result of `verify_log_integrity`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogIntegrity {
+    /// Rows checked, oldest first
+    pub checked: usize,
+    /// First row whose hash doesn't match, `None` if the chain is intact
+    pub first_broken_id: Option<i64>,
+    pub message: Option<String>,
+}
+
+fn chain_hash(prev_hash: &str, log: &ActionLog) -> Result<String> {
+    let fields = (
+        prev_hash,
+        &log.timestamp,
+        &log.action,
+        &log.src_path,
+        &log.dst_path,
+        &log.status,
+        &log.message,
+        &log.batch_id,
+        log.origin.as_str(),
+    );
+    // Rows without an error code hash as they did before the column existed
+    let content = match &log.error_code {
+        Some(code) => serde_json::to_string(&(fields, code))?,
+        None => serde_json::to_string(&fields)?,
+    };
+    Ok(format!("{:x}", Sha256::digest(content.as_bytes())))
+}
+
+fn last_hash(conn: &Connection) -> Result<String> {
+    let hash: Option<Option<String>> = conn
+        .query_row("SELECT hash FROM action_logs ORDER BY id DESC LIMIT 1", [], |row| row.get(0))
+        .optional()?;
+    Ok(hash.flatten().unwrap_or_default())
+}
+
+/// Appends `log` to the chain. Every insert into `action_logs` goes through
+/// here inside an immediate transaction, so no two rows share a predecessor.
+pub(super) fn insert_log(conn: &Connection, log: &ActionLog) -> Result<i64> {
+    let hash = chain_hash(&last_hash(conn)?, log)?;
+    conn.execute(
+        "INSERT INTO action_logs (timestamp, action, src_path, dst_path, status, message, batch_id, undone, origin, error_code, hash)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+        params![
+            log.timestamp,
+            log.action,
+            log.src_path,
+            log.dst_path,
+            log.status,
+            log.message,
+            log.batch_id,
+            log.undone,
+            log.origin.as_str(),
+            log.error_code,
+            hash
+        ],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+/// Seals rows logged before the chain existed, oldest first. Runs as a
+/// migration, so it only reads the columns that existed at that point.
+pub(super) fn seal_existing(conn: &Connection) -> Result<()> {
+    let logs = conn
+        .prepare(
+            "SELECT id, timestamp, action, src_path, dst_path, status, message, batch_id, undone, origin
+             FROM action_logs ORDER BY id ASC",
+        )?
+        .query_map([], |row| {
+            Ok(ActionLog {
+                id: Some(row.get(0)?),
+                timestamp: row.get(1)?,
+                action: row.get(2)?,
+                src_path: row.get(3)?,
+                dst_path: row.get(4)?,
+                status: row.get(5)?,
+                message: row.get(6)?,
+                batch_id: row.get(7)?,
+                undone: row.get(8)?,
+                origin: LogOrigin::parse(&row.get::<_, String>(9)?),
+                error_code: None,
+            })
+        })?
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+
+    let mut prev_hash = String::new();
+    for log in logs {
+        let hash = chain_hash(&prev_hash, &log)?;
+        conn.execute("UPDATE action_logs SET hash = ?1 WHERE id = ?2", params![hash, log.id])?;
+        prev_hash = hash;
+    }
+    Ok(())
+}
+
+impl Database {
+    /// Walks the chain from the oldest row and stops at the first row whose
+    /// stored hash doesn't match its content and predecessor. The anchor the
+    /// walk starts from must be the one the newest prune row recorded.
+    pub fn verify_log_integrity(&self) -> Result<LogIntegrity> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {}, hash FROM action_logs ORDER BY id ASC",
+            LOG_COLUMNS
+        ))?;
+        let rows = stmt.query_map([], |row| Ok((row_to_log(row)?, row.get::<_, Option<String>>(11)?)))?;
+
+        // Rows pruned for retention leave the hash the oldest remaining row chains to
+        let anchor: Option<String> = conn
+            .query_row("SELECT value FROM settings WHERE key = ?1", [CHAIN_ANCHOR_KEY], |row| row.get(0))
+            .optional()?;
+
+        let mut checked = 0;
+        let anchor: String = match anchor {
+            Some(anchor) => serde_json::from_str(&anchor)?,
+            None => String::new(),
+        };
+        let mut prev_hash = anchor.clone();
+        let mut first_id = None;
+        let mut last_prune: Option<(Option<i64>, String)> = None;
+        for row in rows {
+            let (log, stored) = row?;
+            checked += 1;
+            first_id = first_id.or(log.id);
+            if log.action == PRUNE_ACTION && log.origin == LogOrigin::Backend {
+                let recorded = log.message.as_deref().and_then(recorded_anchor).unwrap_or_default();
+                last_prune = Some((log.id, recorded.to_string()));
+            }
+
+            let expected = chain_hash(&prev_hash, &log)?;
+            let message = match &stored {
+                None => Some("row has no hash"),
+                Some(stored) if *stored != expected => Some("content or predecessor changed"),
+                _ => None,
+            };
+            if let Some(message) = message {
+                return Ok(LogIntegrity {
+                    checked,
+                    first_broken_id: log.id,
+                    message: Some(message.to_string()),
+                });
+            }
+
+            prev_hash = stored.unwrap_or_default();
+        }
+
+        let (prune_id, recorded) = last_prune.unwrap_or((first_id, String::new()));
+        if recorded != anchor {
+            return Ok(LogIntegrity {
+                checked,
+                first_broken_id: prune_id,
+                message: Some("chain anchor doesn't match the last prune".to_string()),
+            });
+        }
+
+        Ok(LogIntegrity { checked, first_broken_id: None, message: None })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn log(db: &Database, action: &str) -> i64 {
+        db.log_action(ActionLog {
+            id: None,
+            timestamp: Utc::now().to_rfc3339(),
+            action: action.to_string(),
+            src_path: Some("/a".to_string()),
+            dst_path: Some("/b".to_string()),
+            status: "SUCCESS".to_string(),
+            message: None,
+            batch_id: Some("b1".to_string()),
+            undone: false,
+            origin: LogOrigin::Backend,
+            error_code: None,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_verify_log_integrity() {
+        let db = Database::new(":memory:").unwrap();
+        let first = log(&db, "MOVE");
+        let second = log(&db, "COPY");
+        log(&db, "RENAME");
+
+        // Undo and redo don't break the chain
+        db.set_undone(&[second], true).unwrap();
+        let report = db.verify_log_integrity().unwrap();
+        assert_eq!((report.checked, report.first_broken_id), (3, None));
+
+        let conn = db.conn().unwrap();
+        conn.execute("UPDATE action_logs SET src_path = '/elsewhere' WHERE id = ?1", [second]).unwrap();
+        drop(conn);
+        assert_eq!(db.verify_log_integrity().unwrap().first_broken_id, Some(second));
+
+        // A deleted row shows up at the row that followed it
+        let conn = db.conn().unwrap();
+        conn.execute("UPDATE action_logs SET src_path = '/a' WHERE id = ?1", [second]).unwrap();
+        conn.execute("DELETE FROM action_logs WHERE id = ?1", [first]).unwrap();
+        drop(conn);
+        assert_eq!(db.verify_log_integrity().unwrap().first_broken_id, Some(second));
+    }
+}
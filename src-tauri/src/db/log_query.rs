@@ -0,0 +1,198 @@
+use super::{escape_like, row_to_log, ActionLog, Database, LOG_COLUMNS};
+use crate::error::{AppError, Result};
+use chrono::{DateTime, SecondsFormat, Utc};
+use rusqlite::types::ToSql;
+use serde::{Deserialize, Serialize};
+
+/// Rows returned by `query_logs` unless the caller sets its own limit.
+const DEFAULT_LIMIT: usize = 200;
+
+/// WHERE conditions with the values of their placeholders, in order.
+pub(super) type Conditions = (Vec<String>, Vec<Box<dyn ToSql>>);
+
+/// Optional narrowing of the action log. Everything left out matches.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LogFilters {
+    /// Any of these actions, e.g. `MOVE`
+    pub actions: Vec<String>,
+    /// Any of these statuses, e.g. `ERROR`
+    pub statuses: Vec<String>,
+    /// Rows whose source or destination is this path or lies below it
+    pub path_prefix: Option<String>,
+    pub batch_id: Option<String>,
+    /// RFC 3339 timestamps, both inclusive
+    pub since: Option<String>,
+    pub until: Option<String>,
+}
+
+/// One page of the action log, newest first.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogPage {
+    pub logs: Vec<ActionLog>,
+    /// Rows matching the filters across all pages
+    pub total: usize,
+    /// Cursor of the next page, `None` on the last one
+    pub next_cursor: Option<i64>,
+}
+
+/// `value` in the form the backend writes timestamps in, so that bounds
+/// compare correctly against the stored text.
+fn normalize_time(value: &str) -> Result<String> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|t| t.with_timezone(&Utc).to_rfc3339_opts(SecondsFormat::AutoSi, false))
+        .map_err(|e| AppError::InvalidQuery(format!("{}: {}", value, e)))
+}
+
+impl LogFilters {
+    pub(super) fn conditions(&self) -> Result<Conditions> {
+        let mut conditions: Vec<String> = Vec::new();
+        let mut values: Vec<Box<dyn ToSql>> = Vec::new();
+
+        if !self.actions.is_empty() {
+            conditions.push(format!("action IN ({})", vec!["?"; self.actions.len()].join(", ")));
+            for action in &self.actions {
+                values.push(Box::new(action.clone()));
+            }
+        }
+        if !self.statuses.is_empty() {
+            conditions.push(format!("status IN ({})", vec!["?"; self.statuses.len()].join(", ")));
+            for status in &self.statuses {
+                values.push(Box::new(status.clone()));
+            }
+        }
+        if let Some(prefix) = &self.path_prefix {
+            let prefix = prefix.trim_end_matches('/');
+            let below = format!("{}/%", escape_like(prefix));
+            conditions.push(
+                "(src_path = ? OR src_path LIKE ? ESCAPE '\\' OR dst_path = ? OR dst_path LIKE ? ESCAPE '\\')".to_string(),
+            );
+            values.push(Box::new(prefix.to_string()));
+            values.push(Box::new(below.clone()));
+            values.push(Box::new(prefix.to_string()));
+            values.push(Box::new(below));
+        }
+        if let Some(batch_id) = &self.batch_id {
+            conditions.push("batch_id = ?".to_string());
+            values.push(Box::new(batch_id.clone()));
+        }
+        if let Some(since) = &self.since {
+            conditions.push("timestamp >= ?".to_string());
+            values.push(Box::new(normalize_time(since)?));
+        }
+        if let Some(until) = &self.until {
+            conditions.push("timestamp <= ?".to_string());
+            values.push(Box::new(normalize_time(until)?));
+        }
+
+        Ok((conditions, values))
+    }
+}
+
+pub(super) fn where_clause(conditions: &[String]) -> String {
+    if conditions.is_empty() {
+        String::new()
+    } else {
+        format!("WHERE {}", conditions.join(" AND "))
+    }
+}
+
+impl Database {
+    /// Rows matching `filters`, newest first. `cursor` is the `next_cursor`
+    /// of the previous page; rows logged since then don't shift later pages.
+    pub fn query_logs(&self, filters: &LogFilters, cursor: Option<i64>, limit: Option<usize>) -> Result<LogPage> {
+        let limit = limit.unwrap_or(DEFAULT_LIMIT).max(1);
+        let (mut conditions, mut values) = filters.conditions()?;
+
+        let conn = self.conn()?;
+        let total: usize = conn.query_row(
+            &format!("SELECT COUNT(*) FROM action_logs {}", where_clause(&conditions)),
+            rusqlite::params_from_iter(values.iter()),
+            |row| row.get(0),
+        )?;
+
+        if let Some(cursor) = cursor {
+            conditions.push("id < ?".to_string());
+            values.push(Box::new(cursor));
+        }
+        // One extra row tells whether there is a next page
+        values.push(Box::new((limit + 1) as i64));
+
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM action_logs
+             {}
+             ORDER BY id DESC
+             LIMIT ?",
+            LOG_COLUMNS,
+            where_clause(&conditions)
+        ))?;
+        let mut logs = stmt
+            .query_map(rusqlite::params_from_iter(values.iter()), row_to_log)?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        let next_cursor = if logs.len() > limit {
+            logs.truncate(limit);
+            logs.last().and_then(|log| log.id)
+        } else {
+            None
+        };
+
+        Ok(LogPage { logs, total, next_cursor })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::LogOrigin;
+
+    fn log(db: &Database, action: &str, status: &str, src_path: &str, batch_id: &str) {
+        db.log_action(ActionLog {
+            id: None,
+            timestamp: Utc::now().to_rfc3339(),
+            action: action.to_string(),
+            src_path: Some(src_path.to_string()),
+            dst_path: None,
+            status: status.to_string(),
+            message: None,
+            batch_id: Some(batch_id.to_string()),
+            undone: false,
+            origin: LogOrigin::Backend,
+            error_code: None,
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn test_query_logs() {
+        let db = Database::new(":memory:").unwrap();
+        for i in 0..5 {
+            log(&db, "MOVE", "SUCCESS", &format!("/data/a{}", i), "b1");
+        }
+        log(&db, "DELETE", "ERROR", "/data_other/x", "b2");
+        log(&db, "DELETE", "SUCCESS", "/data", "b3");
+
+        // Pages follow each other without overlap and report the full count
+        let moves = LogFilters { actions: vec!["MOVE".to_string()], ..Default::default() };
+        let first = db.query_logs(&moves, None, Some(3)).unwrap();
+        assert_eq!(first.total, 5);
+        assert_eq!(first.logs.len(), 3);
+        let second = db.query_logs(&moves, first.next_cursor, Some(3)).unwrap();
+        assert_eq!(second.logs.len(), 2);
+        assert!(second.next_cursor.is_none());
+        assert!(second.logs[0].id < first.logs[2].id);
+
+        // The prefix matches the path itself and what lies below it, not siblings
+        let under = LogFilters { path_prefix: Some("/data/".to_string()), ..Default::default() };
+        assert_eq!(db.query_logs(&under, None, None).unwrap().total, 6);
+
+        let errors = LogFilters { statuses: vec!["ERROR".to_string()], ..Default::default() };
+        let page = db.query_logs(&errors, None, None).unwrap();
+        assert_eq!(page.logs[0].batch_id.as_deref(), Some("b2"));
+
+        let future = LogFilters { since: Some("2999-01-01T00:00:00Z".to_string()), ..Default::default() };
+        assert_eq!(db.query_logs(&future, None, None).unwrap().total, 0);
+        let bad = LogFilters { until: Some("yesterday".to_string()), ..Default::default() };
+        assert!(matches!(db.query_logs(&bad, None, None), Err(AppError::InvalidQuery(_))));
+    }
+}
@@ -0,0 +1,194 @@
+use super::log_query::where_clause;
+use super::{log_chain, row_to_log, ActionLog, Database, LogFilters, LogOrigin, LOG_COLUMNS};
+use crate::error::{AppError, Result};
+use chrono::{DateTime, Utc};
+use rusqlite::{params, TransactionBehavior};
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, Read, Write};
+
+/// File format of an exported action log. Both carry the `ActionLog` fields
+/// under their serialized names, oldest row first.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+    /// One JSON object per line
+    Jsonl,
+    /// With a header row
+    Csv,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ImportSummary {
+    pub imported: usize,
+    /// Rows already in the log, matched by batch id and timestamp
+    pub skipped: usize,
+}
+
+impl Database {
+    /// Writes the rows matching `filters` to `out` and returns how many there were.
+    pub fn export_logs(&self, filters: &LogFilters, format: LogFormat, out: impl Write) -> Result<usize> {
+        let (conditions, values) = filters.conditions()?;
+
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM action_logs {} ORDER BY id ASC",
+            LOG_COLUMNS,
+            where_clause(&conditions)
+        ))?;
+        let rows = stmt.query_map(rusqlite::params_from_iter(values.iter()), row_to_log)?;
+
+        let mut count = 0;
+        match format {
+            LogFormat::Jsonl => {
+                let mut out = out;
+                for log in rows {
+                    serde_json::to_writer(&mut out, &log?)?;
+                    out.write_all(b"\n")?;
+                    count += 1;
+                }
+                out.flush()?;
+            }
+            LogFormat::Csv => {
+                let mut writer = csv::Writer::from_writer(out);
+                for log in rows {
+                    writer.serialize(log?)?;
+                    count += 1;
+                }
+                writer.flush()?;
+            }
+        }
+        Ok(count)
+    }
+
+    /// Merges an exported log into this one. Imported rows keep their
+    /// timestamps, converted to UTC like the ones logged here, but never take
+    /// part in undo or redo here. Nothing is imported if any row fails to
+    /// parse.
+    pub fn import_logs(&self, format: LogFormat, input: impl Read) -> Result<ImportSummary> {
+        let logs = match format {
+            LogFormat::Jsonl => {
+                let mut logs = Vec::new();
+                for (i, line) in BufReader::new(input).lines().enumerate() {
+                    let line = line?;
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+                    let log: ActionLog = serde_json::from_str(&line)
+                        .map_err(|e| AppError::InvalidData(format!("line {}: {}", i + 1, e)))?;
+                    logs.push(log);
+                }
+                logs
+            }
+            LogFormat::Csv => {
+                let mut logs = Vec::new();
+                for (i, record) in csv::Reader::from_reader(input).deserialize().enumerate() {
+                    let log: ActionLog = record.map_err(|e| {
+                        if e.is_io_error() {
+                            AppError::from(e)
+                        } else {
+                            AppError::InvalidData(format!("row {}: {}", i + 1, e))
+                        }
+                    })?;
+                    logs.push(log);
+                }
+                logs
+            }
+        };
+
+        let logs = logs
+            .into_iter()
+            .map(|log| {
+                let timestamp = DateTime::parse_from_rfc3339(&log.timestamp)
+                    .map_err(|e| AppError::InvalidData(format!("{}: {}", log.timestamp, e)))?
+                    .with_timezone(&Utc)
+                    .to_rfc3339();
+                Ok(ActionLog { timestamp, ..log })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut summary = ImportSummary::default();
+        let mut conn = self.conn()?;
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        for log in logs {
+            let exists = tx
+                .prepare_cached("SELECT 1 FROM action_logs WHERE batch_id IS ?1 AND timestamp = ?2")?
+                .exists(params![log.batch_id, log.timestamp])?;
+            if exists {
+                summary.skipped += 1;
+                continue;
+            }
+
+            log_chain::insert_log(&tx, &ActionLog { origin: LogOrigin::Imported, ..log })?;
+            summary.imported += 1;
+        }
+        tx.commit()?;
+
+        Ok(summary)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn log(db: &Database, action: &str, batch_id: &str) {
+        db.log_action(ActionLog {
+            id: None,
+            timestamp: Utc::now().to_rfc3339(),
+            action: action.to_string(),
+            src_path: Some("/data/a, \"quoted\"".to_string()),
+            dst_path: None,
+            status: "SUCCESS".to_string(),
+            message: Some("two\nlines".to_string()),
+            batch_id: Some(batch_id.to_string()),
+            undone: false,
+            origin: LogOrigin::Backend,
+            error_code: None,
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn test_export_import_round_trip() {
+        for format in [LogFormat::Jsonl, LogFormat::Csv] {
+            let source = Database::new(":memory:").unwrap();
+            log(&source, "MOVE", "b1");
+            log(&source, "COPY", "b2");
+
+            let mut exported = Vec::new();
+            assert_eq!(source.export_logs(&LogFilters::default(), format, &mut exported).unwrap(), 2);
+
+            let target = Database::new(":memory:").unwrap();
+            log(&target, "RENAME", "b3");
+            let summary = target.import_logs(format, exported.as_slice()).unwrap();
+            assert_eq!((summary.imported, summary.skipped), (2, 0));
+
+            // Importing the same history again adds nothing
+            let summary = target.import_logs(format, exported.as_slice()).unwrap();
+            assert_eq!((summary.imported, summary.skipped), (0, 2));
+
+            let logs = target.get_recent_logs(10).unwrap();
+            assert_eq!(logs.len(), 3);
+            assert_eq!(logs[0].src_path.as_deref(), Some("/data/a, \"quoted\""));
+            assert_eq!(logs[0].message.as_deref(), Some("two\nlines"));
+            assert_eq!(logs[0].origin, LogOrigin::Imported);
+
+            // Undo still only sees what happened on this machine
+            let last = target.get_last_reversible_action().unwrap();
+            assert_eq!(last[0].batch_id.as_deref(), Some("b3"));
+        }
+    }
+
+    #[test]
+    fn test_import_normalizes_timestamps() {
+        let db = Database::new(":memory:").unwrap();
+        let row = r#"{"id":7,"timestamp":"2024-03-01T12:30:00+02:00","action":"MOVE","src_path":"/a","dst_path":"/b","status":"SUCCESS","message":null,"batch_id":"b1","undone":false,"origin":"backend","error_code":null}"#;
+        db.import_logs(LogFormat::Jsonl, row.as_bytes()).unwrap();
+        assert_eq!(db.get_recent_logs(1).unwrap()[0].timestamp, "2024-03-01T10:30:00+00:00");
+
+        let bad = row.replace("2024-03-01T12:30:00+02:00", "yesterday");
+        assert!(matches!(db.import_logs(LogFormat::Jsonl, bad.as_bytes()), Err(AppError::InvalidData(_))));
+        assert!(matches!(db.import_logs(LogFormat::Jsonl, "{".as_bytes()), Err(AppError::InvalidData(_))));
+        assert!(matches!(db.import_logs(LogFormat::Csv, "id,timestamp\nx,y".as_bytes()), Err(AppError::InvalidData(_))));
+    }
+}
@@ -1,9 +1,25 @@
+mod file_index;
+mod log_chain;
+mod log_query;
+mod log_transfer;
+mod retention;
 pub mod schema;
 
-use crate::error::Result;
-use rusqlite::{Connection, params};
-use std::sync::Mutex;
+pub use file_index::{IndexEntry, IndexFilters, IndexRootStatus, IndexState};
+pub use log_chain::LogIntegrity;
+pub use log_query::{LogFilters, LogPage};
+pub use log_transfer::{ImportSummary, LogFormat};
+pub use retention::{AccessLog, LogRetention, PruneSummary, VacuumResult};
+
+use crate::error::{AppError, Result};
+use crate::fs::sandbox::{AllowedRoot, RootMode};
+use r2d2::{Pool, PooledConnection};
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::{Connection, OptionalExtension, TransactionBehavior, params};
+use std::sync::atomic::AtomicU64;
+use std::sync::Arc;
 use chrono::Utc;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -16,135 +32,457 @@ pub struct ActionLog {
     pub status: String,
     pub message: Option<String>,
     pub batch_id: Option<String>,
+    #[serde(default)]
+    pub undone: bool,
+    /// Only rows the backend logged itself take part in undo and redo
+    #[serde(default)]
+    pub origin: LogOrigin,
+    /// `AppError` code of an `ERROR` row
+    #[serde(default)]
+    pub error_code: Option<String>,
+}
+
+/// Where an `action_logs` row came from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogOrigin {
+    #[default]
+    Backend,
+    /// Submitted by the frontend through `db_log`
+    Client,
+    /// Merged in from another machine's exported log
+    Imported,
 }
 
+impl LogOrigin {
+    fn as_str(&self) -> &'static str {
+        match self {
+            LogOrigin::Backend => "backend",
+            LogOrigin::Client => "client",
+            LogOrigin::Imported => "imported",
+        }
+    }
+
+    fn parse(value: &str) -> Self {
+        match value {
+            "client" => LogOrigin::Client,
+            "imported" => LogOrigin::Imported,
+            _ => LogOrigin::Backend,
+        }
+    }
+}
+
+/// Actions that `undo_last_action` and `redo_last_action` know how to reverse.
+const REVERSIBLE_ACTIONS: &str = "('MOVE', 'COPY', 'RENAME', 'CREATE_DIR', 'DELETE')";
+
+/// Irreversible actions that still end the redo history, since they change
+/// what an undone batch would be reapplied to. Sweeper purges carry their
+/// retention reason as message and only drop what had expired, so they don't.
+const REDO_BARRIER: &str = "(action = 'RESTORE' OR (action = 'PURGE' AND message IS NULL))";
+
+/// DELETE rows whose item has left the trash through a restore or purge
+/// since. Nothing is left to undo for them, so they never hold up undo or redo.
+const LEFT_TRASH: &str = "(action = 'DELETE' AND EXISTS (
+    SELECT 1 FROM action_logs AS later
+    WHERE later.id > action_logs.id AND later.status = 'SUCCESS' AND later.origin = 'backend'
+      AND ((later.action = 'RESTORE' AND later.src_path = action_logs.dst_path)
+        OR (later.action = 'PURGE' AND later.dst_path = action_logs.dst_path))))";
+
+const LOG_COLUMNS: &str = "id, timestamp, action, src_path, dst_path, status, message, batch_id, undone, origin, error_code";
+
+/// `value` with LIKE wildcards escaped for `ESCAPE '\'`.
+fn escape_like(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+}
+
+fn row_to_log(row: &rusqlite::Row) -> rusqlite::Result<ActionLog> {
+    Ok(ActionLog {
+        id: Some(row.get(0)?),
+        timestamp: row.get(1)?,
+        action: row.get(2)?,
+        src_path: row.get(3)?,
+        dst_path: row.get(4)?,
+        status: row.get(5)?,
+        message: row.get(6)?,
+        batch_id: row.get(7)?,
+        undone: row.get(8)?,
+        origin: LogOrigin::parse(&row.get::<_, String>(9)?),
+        error_code: row.get(10)?,
+    })
+}
+
+/// Connections kept open to a file database. Reads run side by side under
+/// WAL; writes still take turns, waiting up to `BUSY_TIMEOUT_MS` each.
+const POOL_SIZE: u32 = 4;
+
+const BUSY_TIMEOUT_MS: u32 = 5000;
+
+fn configure(conn: &mut Connection) -> rusqlite::Result<()> {
+    conn.execute_batch(&format!(
+        "PRAGMA journal_mode = WAL;
+         PRAGMA synchronous = NORMAL;
+         PRAGMA busy_timeout = {};",
+        BUSY_TIMEOUT_MS
+    ))
+}
+
+/// Handle on the app database. Clones share the same connection pool.
+#[derive(Clone)]
 pub struct Database {
-    conn: Mutex<Connection>,
+    pool: Pool<SqliteConnectionManager>,
+    /// Read-only events seen so far, for sampling the access log
+    access_events: Arc<AtomicU64>,
 }
 
 impl Database {
     pub fn new(path: &str) -> Result<Self> {
-        let conn = Connection::open(path)?;
-        let db = Database {
-            conn: Mutex::new(conn),
+        // Every connection to ":memory:" is a database of its own
+        let (manager, size) = match path {
+            ":memory:" => (SqliteConnectionManager::memory(), 1),
+            _ => (SqliteConnectionManager::file(path), POOL_SIZE),
         };
-        db.init()?;
-        Ok(db)
+        let pool = Pool::builder()
+            .max_size(size)
+            .idle_timeout(None)
+            .max_lifetime(None)
+            .build(manager.with_init(configure))?;
+
+        let mut conn = pool.get()?;
+        schema::migrate(&mut conn)?;
+
+        Ok(Database {
+            pool,
+            access_events: Arc::new(AtomicU64::new(0)),
+        })
     }
 
-    pub fn init(&self) -> Result<()> {
-        let conn = self.conn.lock().unwrap();
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS action_logs (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                timestamp TEXT NOT NULL,
-                action TEXT NOT NULL,
-                src_path TEXT,
-                dst_path TEXT,
-                status TEXT NOT NULL,
-                message TEXT,
-                batch_id TEXT
-            )",
+    /// A connection from the pool, blocking until one is free.
+    fn conn(&self) -> Result<PooledConnection<SqliteConnectionManager>> {
+        Ok(self.pool.get()?)
+    }
+
+    /// Runs `f` on a blocking worker, so async commands never wait on
+    /// SQLite themselves.
+    pub async fn run<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Database) -> Result<T> + Send + 'static,
+    {
+        let db = self.clone();
+        tauri::async_runtime::spawn_blocking(move || f(&db))
+            .await
+            .map_err(|e| AppError::Unknown(e.to_string()))?
+    }
+
+    pub fn log_action(&self, log: ActionLog) -> Result<i64> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let id = log_chain::insert_log(&tx, &log)?;
+        tx.commit()?;
+        Ok(id)
+    }
+
+    /// Newest batch that is still applied, i.e. the next one to undo.
+    pub fn get_last_reversible_action(&self) -> Result<Vec<ActionLog>> {
+        let conn = self.conn()?;
+        let last_action = conn.query_row(
+            &format!(
+                "SELECT {} FROM action_logs
+                 WHERE status = 'SUCCESS' AND origin = 'backend' AND action IN {} AND undone = 0
+                   AND NOT {}
+                 ORDER BY id DESC
+                 LIMIT 1",
+                LOG_COLUMNS, REVERSIBLE_ACTIONS, LEFT_TRASH
+            ),
             [],
-        )?;
+            row_to_log,
+        ).optional()?;
+
+        match last_action {
+            Some(action) => Self::batch_rows(&conn, action, false),
+            None => Ok(vec![]),
+        }
+    }
+
+    /// Oldest batch undone on top of the history, i.e. the next one to redo.
+    /// Anything logged after an undo makes the undone batches below it
+    /// unreachable. Rows of the batch itself that are still applied don't,
+    /// so a batch whose undo stopped halfway can be redone.
+    pub fn get_last_undone_action(&self) -> Result<Vec<ActionLog>> {
+        let conn = self.conn()?;
+        let first_undone = conn.query_row(
+            &format!(
+                "SELECT {} FROM action_logs AS candidate
+                 WHERE status = 'SUCCESS' AND origin = 'backend' AND action IN {rev} AND undone = 1
+                   AND id > (SELECT COALESCE(MAX(id), 0) FROM action_logs
+                             WHERE status = 'SUCCESS' AND origin = 'backend' AND undone = 0
+                               AND (action IN {rev} OR {barrier}) AND NOT {left}
+                               AND (candidate.batch_id IS NULL OR batch_id IS NOT candidate.batch_id))
+                 ORDER BY id ASC
+                 LIMIT 1",
+                LOG_COLUMNS,
+                rev = REVERSIBLE_ACTIONS,
+                barrier = REDO_BARRIER,
+                left = LEFT_TRASH
+            ),
+            [],
+            row_to_log,
+        ).optional()?;
+
+        match first_undone {
+            Some(action) => Self::batch_rows(&conn, action, true),
+            None => Ok(vec![]),
+        }
+    }
+
+    /// Every reversible row of a batch, undone or not, oldest first.
+    pub fn get_batch_actions(&self, batch_id: &str) -> Result<Vec<ActionLog>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM action_logs
+             WHERE batch_id = ?1 AND status = 'SUCCESS' AND origin = 'backend' AND action IN {}
+             ORDER BY id ASC",
+            LOG_COLUMNS, REVERSIBLE_ACTIONS
+        ))?;
+
+        let actions = stmt
+            .query_map([batch_id], row_to_log)?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        Ok(actions)
+    }
+
+    /// Flips the rows of a batch together, so no reader sees it half undone.
+    pub fn set_undone(&self, ids: &[i64], undone: bool) -> Result<()> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        for id in ids {
+            tx.execute(
+                "UPDATE action_logs SET undone = ?1 WHERE id = ?2",
+                params![undone, id],
+            )?;
+        }
+        tx.commit()?;
         Ok(())
     }
 
-    pub fn log_action(&self, log: ActionLog) -> Result<i64> {
-        let conn = self.conn.lock().unwrap();
+    /// All rows of `action`'s batch with the given undone state, oldest first.
+    /// Deletes whose item already left the trash don't count as applied.
+    fn batch_rows(conn: &Connection, action: ActionLog, undone: bool) -> Result<Vec<ActionLog>> {
+        let batch_id = match &action.batch_id {
+            Some(batch_id) => batch_id.clone(),
+            None => return Ok(vec![action]),
+        };
+
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM action_logs
+             WHERE batch_id = ?1 AND status = 'SUCCESS' AND origin = 'backend' AND action IN {} AND undone = ?2
+               AND (?2 OR NOT {})
+             ORDER BY id ASC",
+            LOG_COLUMNS, REVERSIBLE_ACTIONS, LEFT_TRASH
+        ))?;
+
+        let actions = stmt
+            .query_map(params![batch_id, undone], row_to_log)?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        Ok(actions)
+    }
+
+    /// Reads a JSON-encoded setting, `None` if it was never set.
+    pub fn get_setting<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>> {
+        let conn = self.conn()?;
+        let value: Option<String> = conn
+            .query_row("SELECT value FROM settings WHERE key = ?1", [key], |row| row.get(0))
+            .optional()?;
+
+        match value {
+            Some(value) => Ok(Some(serde_json::from_str(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn set_setting<T: Serialize>(&self, key: &str, value: &T) -> Result<()> {
+        let conn = self.conn()?;
         conn.execute(
-            "INSERT INTO action_logs (timestamp, action, src_path, dst_path, status, message, batch_id)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
-            params![
-                log.timestamp,
-                log.action,
-                log.src_path,
-                log.dst_path,
-                log.status,
-                log.message,
-                log.batch_id
-            ],
+            "INSERT INTO settings (key, value) VALUES (?1, ?2)
+             ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+            params![key, serde_json::to_string(value)?],
         )?;
-        Ok(conn.last_insert_rowid())
+        Ok(())
     }
 
-    pub fn get_last_reversible_action(&self) -> Result<Vec<ActionLog>> {
-        let conn = self.conn.lock().unwrap();
-        let mut stmt = conn.prepare(
-            "SELECT id, timestamp, action, src_path, dst_path, status, message, batch_id
-             FROM action_logs
-             WHERE status = 'SUCCESS' AND action IN ('MOVE', 'RENAME', 'CREATE_DIR', 'DELETE')
-             ORDER BY id DESC
-             LIMIT 1"
-        )?;
+    pub fn get_allowed_roots(&self) -> Result<Vec<AllowedRoot>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare("SELECT path, mode FROM allowed_roots ORDER BY rowid")?;
 
-        let last_action = stmt.query_row([], |row| {
-            Ok(ActionLog {
-                id: Some(row.get(0)?),
-                timestamp: row.get(1)?,
-                action: row.get(2)?,
-                src_path: row.get(3)?,
-                dst_path: row.get(4)?,
-                status: row.get(5)?,
-                message: row.get(6)?,
-                batch_id: row.get(7)?,
-            })
-        }).ok();
-
-        if let Some(action) = last_action {
-            if let Some(batch_id) = action.batch_id {
-                // Get all actions in the batch
-                let mut stmt = conn.prepare(
-                    "SELECT id, timestamp, action, src_path, dst_path, status, message, batch_id
-                     FROM action_logs
-                     WHERE batch_id = ?1
-                     ORDER BY id ASC"
-                )?;
-
-                let actions = stmt.query_map([batch_id], |row| {
-                    Ok(ActionLog {
-                        id: Some(row.get(0)?),
-                        timestamp: row.get(1)?,
-                        action: row.get(2)?,
-                        src_path: row.get(3)?,
-                        dst_path: row.get(4)?,
-                        status: row.get(5)?,
-                        message: row.get(6)?,
-                        batch_id: row.get(7)?,
-                    })
-                })?.collect::<std::result::Result<Vec<_>, _>>()?;
-
-                Ok(actions)
-            } else {
-                Ok(vec![action])
-            }
-        } else {
-            Ok(vec![])
+        let roots = stmt
+            .query_map([], |row| {
+                Ok(AllowedRoot {
+                    path: row.get(0)?,
+                    mode: RootMode::parse(&row.get::<_, String>(1)?),
+                })
+            })?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        Ok(roots)
+    }
+
+    /// Replaces the stored roots with `roots`.
+    pub fn set_allowed_roots(&self, roots: &[AllowedRoot]) -> Result<()> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        tx.execute("DELETE FROM allowed_roots", [])?;
+        for root in roots {
+            tx.execute(
+                "INSERT INTO allowed_roots (path, mode) VALUES (?1, ?2)
+                 ON CONFLICT(path) DO UPDATE SET mode = excluded.mode",
+                params![root.path, root.mode.as_str()],
+            )?;
         }
+        tx.commit()?;
+        Ok(())
     }
 
     pub fn get_recent_logs(&self, limit: usize) -> Result<Vec<ActionLog>> {
-        let conn = self.conn.lock().unwrap();
-        let mut stmt = conn.prepare(
-            "SELECT id, timestamp, action, src_path, dst_path, status, message, batch_id
-             FROM action_logs
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM action_logs
              ORDER BY id DESC
-             LIMIT ?1"
-        )?;
+             LIMIT ?1",
+            LOG_COLUMNS
+        ))?;
 
-        let logs = stmt.query_map([limit], |row| {
-            Ok(ActionLog {
-                id: Some(row.get(0)?),
-                timestamp: row.get(1)?,
-                action: row.get(2)?,
-                src_path: row.get(3)?,
-                dst_path: row.get(4)?,
-                status: row.get(5)?,
-                message: row.get(6)?,
-                batch_id: row.get(7)?,
-            })
-        })?.collect::<std::result::Result<Vec<_>, _>>()?;
+        let logs = stmt.query_map([limit], row_to_log)?.collect::<std::result::Result<Vec<_>, _>>()?;
 
         Ok(logs)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn log(db: &Database, action: &str, batch_id: &str) -> i64 {
+        db.log_action(ActionLog {
+            id: None,
+            timestamp: Utc::now().to_rfc3339(),
+            action: action.to_string(),
+            src_path: Some("/a".to_string()),
+            dst_path: Some("/b".to_string()),
+            status: "SUCCESS".to_string(),
+            message: None,
+            batch_id: Some(batch_id.to_string()),
+            undone: false,
+            origin: LogOrigin::Backend,
+            error_code: None,
+        })
+        .unwrap()
+    }
+
+    fn batch_of(actions: Vec<ActionLog>) -> Option<String> {
+        actions.first().and_then(|a| a.batch_id.clone())
+    }
+
+    #[test]
+    fn test_undo_redo_history() {
+        let db = Database::new(":memory:").unwrap();
+        let a1 = log(&db, "MOVE", "b1");
+        let a2 = log(&db, "COPY", "b2");
+        let a3 = log(&db, "RENAME", "b3");
+        log(&db, "LIST_DIR", "ignored");
+
+        assert_eq!(batch_of(db.get_last_reversible_action().unwrap()).as_deref(), Some("b3"));
+        db.set_undone(&[a3], true).unwrap();
+        assert_eq!(batch_of(db.get_last_reversible_action().unwrap()).as_deref(), Some("b2"));
+        db.set_undone(&[a2], true).unwrap();
+
+        // Redo walks forward again, starting with the oldest undone batch
+        assert_eq!(batch_of(db.get_last_undone_action().unwrap()).as_deref(), Some("b2"));
+        db.set_undone(&[a2], false).unwrap();
+        assert_eq!(batch_of(db.get_last_undone_action().unwrap()).as_deref(), Some("b3"));
+
+        // A new action drops everything still undone from the redo history
+        log(&db, "MOVE", "b4");
+        assert!(db.get_last_undone_action().unwrap().is_empty());
+
+        db.set_undone(&[a1], true).unwrap();
+        assert_eq!(batch_of(db.get_last_reversible_action().unwrap()).as_deref(), Some("b4"));
+
+        // A batch whose undo stopped halfway can still be redone
+        log(&db, "MOVE", "b5");
+        let a6 = log(&db, "MOVE", "b5");
+        db.set_undone(&[a6], true).unwrap();
+        let redo = db.get_last_undone_action().unwrap();
+        assert_eq!((batch_of(redo.clone()).as_deref(), redo[0].id), (Some("b5"), Some(a6)));
+    }
+
+    #[test]
+    fn test_trash_actions_in_history() {
+        let db = Database::new(":memory:").unwrap();
+        let trash_row = |action: &str, dst: &str, message: Option<&str>, batch_id: &str| {
+            db.log_action(ActionLog {
+                id: None,
+                timestamp: Utc::now().to_rfc3339(),
+                action: action.to_string(),
+                src_path: Some("/a".to_string()),
+                dst_path: Some(dst.to_string()),
+                status: "SUCCESS".to_string(),
+                message: message.map(str::to_string),
+                batch_id: Some(batch_id.to_string()),
+                undone: false,
+                origin: LogOrigin::Backend,
+                error_code: None,
+            })
+            .unwrap()
+        };
+
+        let a1 = log(&db, "MOVE", "b1");
+        trash_row("DELETE", "/trash/x", None, "b2");
+        let kept = trash_row("DELETE", "/trash/y", None, "b2");
+
+        // A purged item leaves nothing of its delete to undo
+        trash_row("PURGE", "/trash/x", Some("Trash over its size limit"), "s1");
+        let undo = db.get_last_reversible_action().unwrap();
+        assert_eq!(undo.iter().map(|a| a.id).collect::<Vec<_>>(), vec![Some(kept)]);
+        db.set_undone(&[kept], true).unwrap();
+        assert_eq!(batch_of(db.get_last_reversible_action().unwrap()).as_deref(), Some("b1"));
+
+        // Sweeps don't end the redo history, a purge by hand does
+        db.set_undone(&[a1], true).unwrap();
+        trash_row("PURGE", "/trash/z", Some("Older than 30 days"), "s2");
+        assert_eq!(batch_of(db.get_last_undone_action().unwrap()).as_deref(), Some("b1"));
+        trash_row("PURGE", "/trash/w", None, "p1");
+        assert!(db.get_last_undone_action().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_concurrent_logging_on_wal() {
+        let dir = std::env::temp_dir().join(format!("fm-wal-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let db = Database::new(dir.join("filemanager.db").to_str().unwrap()).unwrap();
+
+        let mode: String = db.conn().unwrap().query_row("PRAGMA journal_mode", [], |row| row.get(0)).unwrap();
+        assert_eq!(mode, "wal");
+
+        let writers: Vec<_> = (0..4)
+            .map(|i| {
+                let db = db.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..10 {
+                        log(&db, "MOVE", &format!("b{}", i));
+                        db.get_recent_logs(5).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        // Writers on separate connections still chain one after another
+        let report = db.verify_log_integrity().unwrap();
+        assert_eq!((report.checked, report.first_broken_id), (40, None));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
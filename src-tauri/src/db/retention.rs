@@ -0,0 +1,319 @@
+use super::{log_chain, ActionLog, Database, LogOrigin};
+use crate::error::Result;
+use chrono::Utc;
+use rusqlite::{params, Connection, OptionalExtension, TransactionBehavior};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::Ordering;
+
+/// Settings key of the hash the oldest remaining `action_logs` row chains to.
+pub(super) const CHAIN_ANCHOR_KEY: &str = "log_chain_anchor";
+
+/// Action of the row every prune appends to `action_logs`.
+pub(super) const PRUNE_ACTION: &str = "PRUNE_LOGS";
+
+/// Newest `action_logs` rows no retention setting can remove.
+const MIN_ACTION_LOGS: usize = 100;
+
+const ANCHOR_LABEL: &str = "; chain anchor ";
+
+/// The anchor a prune row recorded, for checking the stored one against.
+pub(super) fn recorded_anchor(message: &str) -> Option<&str> {
+    message.rsplit_once(ANCHOR_LABEL).map(|(_, anchor)| anchor)
+}
+
+/// How successful read-only events such as directory listings are recorded.
+/// They never go to `action_logs`, where they would bury the mutations.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum AccessLogging {
+    Off,
+    /// Every event goes to the access log
+    #[default]
+    AccessLog,
+    /// One event in `every` goes to the access log
+    Sampled { every: u32 },
+}
+
+/// Limits enforced by the log pruner. `None` means unlimited. The newest
+/// `MIN_ACTION_LOGS` action rows are kept whatever the limits say.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LogRetention {
+    pub access: AccessLogging,
+    pub max_age_days: Option<u32>,
+    /// Rows kept per table, newest first
+    pub max_rows: Option<usize>,
+}
+
+/// One row of the access log.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccessLog {
+    pub id: i64,
+    pub timestamp: String,
+    pub action: String,
+    pub path: String,
+    /// The row stands for this many events when sampling
+    pub sample_every: u32,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PruneSummary {
+    pub action_logs: usize,
+    pub access_logs: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VacuumResult {
+    pub bytes_before: u64,
+    pub bytes_after: u64,
+}
+
+fn database_size(conn: &Connection) -> Result<u64> {
+    let size: i64 = conn.query_row(
+        "SELECT page_count * page_size FROM pragma_page_count(), pragma_page_size()",
+        [],
+        |row| row.get(0),
+    )?;
+    Ok(size as u64)
+}
+
+/// Lowest id that survives `retention` in `table`, `None` if no limit is
+/// set or no more than `floor` rows exist. Rows only ever go from the
+/// oldest id up, so the hash chain stays unbroken from the first remaining
+/// row on.
+fn first_kept_id(conn: &Connection, table: &str, retention: &LogRetention, floor: usize) -> Result<Option<i64>> {
+    let mut first_kept: Option<i64> = None;
+
+    if let Some(days) = retention.max_age_days {
+        let cutoff = (Utc::now() - chrono::Duration::days(days as i64)).to_rfc3339();
+        // Only the old rows before the first recent one go, so imported rows
+        // with old timestamps don't punch holes into the chain
+        let first_recent: Option<i64> = conn.query_row(
+            &format!("SELECT MIN(id) FROM {} WHERE timestamp >= ?1", table),
+            [&cutoff],
+            |row| row.get(0),
+        )?;
+        first_kept = match first_recent {
+            Some(id) => Some(id),
+            None => conn.query_row(&format!("SELECT MAX(id) + 1 FROM {}", table), [], |row| row.get(0))?,
+        };
+    }
+
+    if let Some(max_rows) = retention.max_rows {
+        let by_count: Option<i64> = if max_rows == 0 {
+            conn.query_row(&format!("SELECT MAX(id) + 1 FROM {}", table), [], |row| row.get(0))?
+        } else {
+            conn.query_row(
+                &format!("SELECT id FROM {} ORDER BY id DESC LIMIT 1 OFFSET ?1", table),
+                [max_rows as i64 - 1],
+                |row| row.get(0),
+            )
+            .optional()?
+        };
+        first_kept = first_kept.max(by_count);
+    }
+
+    if floor > 0 && first_kept.is_some() {
+        let floor_id: Option<i64> = conn
+            .query_row(
+                &format!("SELECT id FROM {} ORDER BY id DESC LIMIT 1 OFFSET ?1", table),
+                [floor as i64 - 1],
+                |row| row.get(0),
+            )
+            .optional()?;
+        first_kept = match floor_id {
+            Some(floor_id) => first_kept.min(Some(floor_id)),
+            None => None,
+        };
+    }
+
+    Ok(first_kept)
+}
+
+impl Database {
+    /// Records a successful read-only event as `logging` says.
+    pub fn log_access(&self, action: &str, path: &str, logging: AccessLogging) -> Result<()> {
+        let sample_every = match logging {
+            AccessLogging::Off => return Ok(()),
+            AccessLogging::AccessLog => 1,
+            AccessLogging::Sampled { every } => every.max(1),
+        };
+        if !self.access_events.fetch_add(1, Ordering::Relaxed).is_multiple_of(sample_every as u64) {
+            return Ok(());
+        }
+
+        let conn = self.conn()?;
+        conn.execute(
+            "INSERT INTO access_logs (timestamp, action, path, sample_every) VALUES (?1, ?2, ?3, ?4)",
+            params![Utc::now().to_rfc3339(), action, path, sample_every],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_access_logs(&self, limit: usize) -> Result<Vec<AccessLog>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT id, timestamp, action, path, sample_every FROM access_logs
+             ORDER BY id DESC
+             LIMIT ?1",
+        )?;
+
+        let logs = stmt
+            .query_map([limit], |row| {
+                Ok(AccessLog {
+                    id: row.get(0)?,
+                    timestamp: row.get(1)?,
+                    action: row.get(2)?,
+                    path: row.get(3)?,
+                    sample_every: row.get(4)?,
+                })
+            })?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        Ok(logs)
+    }
+
+    /// Deletes the oldest rows of both logs until they are within the age
+    /// and count limits. The hash of the newest deleted action row is kept,
+    /// so the remaining chain can still be verified, and a prune that
+    /// deleted anything is logged with its counts and that hash.
+    pub fn prune_logs(&self, retention: &LogRetention) -> Result<PruneSummary> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let mut summary = PruneSummary::default();
+
+        if let Some(first_kept) = first_kept_id(&tx, "action_logs", retention, MIN_ACTION_LOGS)? {
+            let anchor: Option<Option<String>> = tx
+                .query_row(
+                    "SELECT hash FROM action_logs WHERE id < ?1 ORDER BY id DESC LIMIT 1",
+                    [first_kept],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            if let Some(anchor) = anchor {
+                tx.execute(
+                    "INSERT INTO settings (key, value) VALUES (?1, ?2)
+                     ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+                    params![CHAIN_ANCHOR_KEY, serde_json::to_string(&anchor.unwrap_or_default())?],
+                )?;
+            }
+            summary.action_logs = tx.execute("DELETE FROM action_logs WHERE id < ?1", [first_kept])?;
+        }
+
+        if let Some(first_kept) = first_kept_id(&tx, "access_logs", retention, 0)? {
+            summary.access_logs = tx.execute("DELETE FROM access_logs WHERE id < ?1", [first_kept])?;
+        }
+
+        if summary.action_logs + summary.access_logs > 0 {
+            let anchor: Option<String> = tx
+                .query_row("SELECT value FROM settings WHERE key = ?1", [CHAIN_ANCHOR_KEY], |row| row.get(0))
+                .optional()?;
+            let anchor: String = match anchor {
+                Some(anchor) => serde_json::from_str(&anchor)?,
+                None => String::new(),
+            };
+            log_chain::insert_log(&tx, &ActionLog {
+                id: None,
+                timestamp: Utc::now().to_rfc3339(),
+                action: PRUNE_ACTION.to_string(),
+                src_path: None,
+                dst_path: None,
+                status: "SUCCESS".to_string(),
+                message: Some(format!(
+                    "Pruned {} action log rows and {} access log rows{}{}",
+                    summary.action_logs, summary.access_logs, ANCHOR_LABEL, anchor
+                )),
+                batch_id: None,
+                undone: false,
+                origin: LogOrigin::Backend,
+                error_code: None,
+            })?;
+        }
+
+        tx.commit()?;
+        Ok(summary)
+    }
+
+    /// Rebuilds the database file to hand the space of deleted rows back.
+    pub fn vacuum(&self) -> Result<VacuumResult> {
+        let conn = self.conn()?;
+        let bytes_before = database_size(&conn)?;
+        conn.execute_batch("VACUUM")?;
+        let bytes_after = database_size(&conn)?;
+        Ok(VacuumResult { bytes_before, bytes_after })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{ActionLog, LogOrigin};
+
+    fn log(db: &Database, timestamp: String) {
+        db.log_action(ActionLog {
+            id: None,
+            timestamp,
+            action: "MOVE".to_string(),
+            src_path: Some("/a".to_string()),
+            dst_path: Some("/b".to_string()),
+            status: "SUCCESS".to_string(),
+            message: None,
+            batch_id: None,
+            undone: false,
+            origin: LogOrigin::Backend,
+            error_code: None,
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn test_prune_keeps_chain_verifiable() {
+        let db = Database::new(":memory:").unwrap();
+        let old = (Utc::now() - chrono::Duration::days(40)).to_rfc3339();
+        for _ in 0..3 {
+            log(&db, old.clone());
+        }
+        for _ in 0..MIN_ACTION_LOGS + 20 {
+            log(&db, Utc::now().to_rfc3339());
+        }
+
+        let by_age = LogRetention { max_age_days: Some(30), ..Default::default() };
+        assert_eq!(db.prune_logs(&by_age).unwrap().action_logs, 3);
+        // Even a limit of no rows leaves the newest ones, the prune rows among them
+        let by_count = LogRetention { max_rows: Some(0), ..Default::default() };
+        assert_eq!(db.prune_logs(&by_count).unwrap().action_logs, 21);
+        assert_eq!(db.prune_logs(&LogRetention::default()).unwrap().action_logs, 0);
+
+        let logs = db.get_recent_logs(MIN_ACTION_LOGS + 10).unwrap();
+        assert_eq!(logs.len(), MIN_ACTION_LOGS + 1);
+        assert_eq!(logs[0].action, PRUNE_ACTION);
+        assert!(logs[0].message.as_deref().unwrap().starts_with("Pruned 21 action log rows"));
+
+        let report = db.verify_log_integrity().unwrap();
+        assert_eq!((report.checked, report.first_broken_id), (MIN_ACTION_LOGS + 1, None));
+
+        // Deleting more rows and moving the stored anchor along gets noticed
+        let conn = db.conn().unwrap();
+        let (first, hash): (i64, String) = conn
+            .query_row("SELECT id, hash FROM action_logs ORDER BY id ASC LIMIT 1", [], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap();
+        conn.execute("DELETE FROM action_logs WHERE id = ?1", [first]).unwrap();
+        drop(conn);
+        db.set_setting(CHAIN_ANCHOR_KEY, &hash).unwrap();
+        assert_eq!(db.verify_log_integrity().unwrap().first_broken_id, Some(logs[0].id.unwrap()));
+    }
+
+    #[test]
+    fn test_access_logging_modes() {
+        let db = Database::new(":memory:").unwrap();
+        for _ in 0..6 {
+            db.log_access("LIST_DIR", "/data", AccessLogging::Sampled { every: 3 }).unwrap();
+        }
+        db.log_access("LIST_DIR", "/data", AccessLogging::Off).unwrap();
+
+        let logs = db.get_access_logs(10).unwrap();
+        assert_eq!(logs.len(), 2);
+        assert_eq!(logs[0].sample_every, 3);
+        assert!(db.get_recent_logs(10).unwrap().is_empty());
+    }
+}
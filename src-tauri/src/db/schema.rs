@@ -1,11 +1,375 @@
-use serde::{Deserialize, Serialize};
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct LogEntry {
-    pub action: String,
-    pub src_path: Option<String>,
-    pub dst_path: Option<String>,
-    pub status: String,
-    pub message: Option<String>,
-    pub batch_id: Option<String>,
-}
\ No newline at end of file
+//! Versioned schema of `filemanager.db`.
+//!
+//! Every change to the schema is a new entry at the end of `MIGRATIONS`;
+//! released entries are never edited. Databases written before versioning
+//! was introduced have no `schema_version` table and start at version 0, so
+//! the early migrations tolerate tables and columns that already exist.
+
+use crate::error::{AppError, Result};
+use chrono::Utc;
+use rusqlite::{params, Connection, Transaction};
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Copy)]
+struct Migration {
+    version: u32,
+    description: &'static str,
+    up: fn(&Transaction) -> Result<()>,
+}
+
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "action log",
+        up: |tx| {
+            Ok(tx.execute_batch(
+                "CREATE TABLE IF NOT EXISTS action_logs (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    timestamp TEXT NOT NULL,
+                    action TEXT NOT NULL,
+                    src_path TEXT,
+                    dst_path TEXT,
+                    status TEXT NOT NULL,
+                    message TEXT,
+                    batch_id TEXT
+                );",
+            )?)
+        },
+    },
+    Migration {
+        version: 2,
+        description: "undone flag for undo/redo",
+        up: |tx| add_column(tx, "action_logs", "undone", "INTEGER NOT NULL DEFAULT 0"),
+    },
+    Migration {
+        version: 3,
+        description: "settings",
+        up: |tx| {
+            Ok(tx.execute_batch(
+                "CREATE TABLE IF NOT EXISTS settings (
+                    key TEXT PRIMARY KEY,
+                    value TEXT NOT NULL
+                );",
+            )?)
+        },
+    },
+    Migration {
+        version: 4,
+        description: "file name index",
+        up: |tx| {
+            Ok(tx.execute_batch(
+                "CREATE TABLE IF NOT EXISTS file_entries (
+                    id INTEGER PRIMARY KEY,
+                    path TEXT NOT NULL UNIQUE,
+                    root TEXT NOT NULL,
+                    name TEXT NOT NULL,
+                    ext TEXT,
+                    size INTEGER NOT NULL,
+                    mtime INTEGER NOT NULL,
+                    is_dir INTEGER NOT NULL,
+                    seen INTEGER NOT NULL
+                );
+                CREATE INDEX IF NOT EXISTS idx_file_entries_root ON file_entries(root, seen);
+
+                CREATE VIRTUAL TABLE IF NOT EXISTS file_index USING fts5(
+                    name, content = 'file_entries', content_rowid = 'id', tokenize = 'trigram'
+                );
+                CREATE TRIGGER IF NOT EXISTS file_entries_ai AFTER INSERT ON file_entries BEGIN
+                    INSERT INTO file_index (rowid, name) VALUES (new.id, new.name);
+                END;
+                CREATE TRIGGER IF NOT EXISTS file_entries_ad AFTER DELETE ON file_entries BEGIN
+                    INSERT INTO file_index (file_index, rowid, name) VALUES ('delete', old.id, old.name);
+                END;
+                CREATE TRIGGER IF NOT EXISTS file_entries_au AFTER UPDATE OF name ON file_entries BEGIN
+                    INSERT INTO file_index (file_index, rowid, name) VALUES ('delete', old.id, old.name);
+                    INSERT INTO file_index (rowid, name) VALUES (new.id, new.name);
+                END;
+
+                CREATE TABLE IF NOT EXISTS index_roots (
+                    root TEXT PRIMARY KEY,
+                    state TEXT NOT NULL,
+                    last_indexed_at TEXT,
+                    message TEXT
+                );",
+            )?)
+        },
+    },
+    Migration {
+        version: 5,
+        description: "symlink flag in the file index",
+        up: |tx| add_column(tx, "file_entries", "is_symlink", "INTEGER NOT NULL DEFAULT 0"),
+    },
+    Migration {
+        version: 6,
+        description: "allowed roots",
+        up: |tx| {
+            Ok(tx.execute_batch(
+                "CREATE TABLE IF NOT EXISTS allowed_roots (
+                    path TEXT PRIMARY KEY,
+                    mode TEXT NOT NULL
+                );",
+            )?)
+        },
+    },
+    Migration {
+        version: 7,
+        description: "action log indexes",
+        up: |tx| {
+            Ok(tx.execute_batch(
+                "CREATE INDEX IF NOT EXISTS idx_action_logs_timestamp ON action_logs(timestamp);
+                CREATE INDEX IF NOT EXISTS idx_action_logs_batch_id ON action_logs(batch_id);
+                CREATE INDEX IF NOT EXISTS idx_action_logs_action ON action_logs(action);",
+            )?)
+        },
+    },
+    Migration {
+        version: 8,
+        description: "origin of action log rows",
+        up: |tx| add_column(tx, "action_logs", "origin", "TEXT NOT NULL DEFAULT 'backend'"),
+    },
+    Migration {
+        version: 9,
+        description: "hash chain over the action log",
+        up: |tx| {
+            add_column(tx, "action_logs", "hash", "TEXT")?;
+            super::log_chain::seal_existing(tx)
+        },
+    },
+    Migration {
+        version: 10,
+        description: "error codes of failed actions",
+        up: |tx| add_column(tx, "action_logs", "error_code", "TEXT"),
+    },
+    Migration {
+        version: 11,
+        description: "access log for read-only events",
+        up: |tx| {
+            Ok(tx.execute_batch(
+                "CREATE TABLE IF NOT EXISTS access_logs (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    timestamp TEXT NOT NULL,
+                    action TEXT NOT NULL,
+                    path TEXT NOT NULL,
+                    sample_every INTEGER NOT NULL DEFAULT 1
+                );
+                CREATE INDEX IF NOT EXISTS idx_access_logs_timestamp ON access_logs(timestamp);",
+            )?)
+        },
+    },
+];
+
+/// Version `conn` is at, 0 for a database that predates versioning.
+pub fn current_version(conn: &Connection) -> Result<u32> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS schema_version (
+            version INTEGER PRIMARY KEY,
+            description TEXT NOT NULL,
+            applied_at TEXT NOT NULL
+        );",
+    )?;
+    let version: u32 = conn.query_row("SELECT COALESCE(MAX(version), 0) FROM schema_version", [], |row| row.get(0))?;
+    Ok(version)
+}
+
+/// Brings `conn` up to the latest version in one transaction, so a failed
+/// upgrade leaves the database as it was. A file database that already
+/// holds data is copied next to itself first.
+pub fn migrate(conn: &mut Connection) -> Result<()> {
+    run_migrations(conn, MIGRATIONS)
+}
+
+fn run_migrations(conn: &mut Connection, migrations: &[Migration]) -> Result<()> {
+    let version = current_version(conn)?;
+    let latest = migrations.last().map_or(0, |m| m.version);
+
+    if version > latest {
+        return Err(AppError::DatabaseError(format!(
+            "Database schema version {} is newer than this build supports ({})",
+            version, latest
+        )));
+    }
+    if version == latest {
+        return Ok(());
+    }
+
+    if let Some(path) = database_file(conn) {
+        if has_user_tables(conn)? {
+            backup(conn, &backup_path(&path, version))?;
+        }
+    }
+
+    let tx = conn.transaction()?;
+    for migration in migrations.iter().filter(|m| m.version > version) {
+        (migration.up)(&tx).map_err(|e| {
+            AppError::DatabaseError(format!(
+                "Migration {} ({}) failed: {}",
+                migration.version, migration.description, e
+            ))
+        })?;
+        tx.execute(
+            "INSERT INTO schema_version (version, description, applied_at) VALUES (?1, ?2, ?3)",
+            params![migration.version, migration.description, Utc::now().to_rfc3339()],
+        )?;
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+/// Where the copy of a database at `version` is kept before upgrading it.
+pub fn backup_path(db_path: &Path, version: u32) -> PathBuf {
+    let mut name = db_path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".v{}.bak", version));
+    db_path.with_file_name(name)
+}
+
+fn backup(conn: &Connection, to: &Path) -> Result<()> {
+    // VACUUM INTO refuses to overwrite; an older backup of the same version is stale
+    match std::fs::remove_file(to) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+        _ => {}
+    }
+    conn.execute("VACUUM INTO ?1", [to.to_string_lossy()])?;
+    Ok(())
+}
+
+/// `None` for in-memory and temporary databases.
+fn database_file(conn: &Connection) -> Option<PathBuf> {
+    conn.path().filter(|path| !path.is_empty()).map(PathBuf::from)
+}
+
+fn has_user_tables(conn: &Connection) -> Result<bool> {
+    let exists = conn
+        .prepare("SELECT 1 FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%' AND name != 'schema_version'")?
+        .exists([])?;
+    Ok(exists)
+}
+
+/// ALTER TABLE has no IF NOT EXISTS, and unversioned databases may already
+/// have the column.
+fn add_column(tx: &Transaction, table: &str, column: &str, definition: &str) -> Result<()> {
+    let exists = tx
+        .prepare(&format!("SELECT 1 FROM pragma_table_info('{}') WHERE name = ?1", table))?
+        .exists([column])?;
+    if !exists {
+        tx.execute(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition), [])?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::Database;
+
+    /// Version the schema is at once every migration has run.
+    fn latest_version() -> u32 {
+        MIGRATIONS.last().map_or(0, |m| m.version)
+    }
+
+    /// Schema as written by `Database::init` before migrations existed.
+    const UNVERSIONED_SCHEMA: &str = "
+        CREATE TABLE IF NOT EXISTS action_logs (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            timestamp TEXT NOT NULL,
+            action TEXT NOT NULL,
+            src_path TEXT,
+            dst_path TEXT,
+            status TEXT NOT NULL,
+            message TEXT,
+            batch_id TEXT
+        );
+        INSERT INTO action_logs (timestamp, action, src_path, dst_path, status, batch_id)
+            VALUES ('2024-01-01T00:00:00+00:00', 'MOVE', '/a', '/b', 'SUCCESS', 'b1');";
+
+    fn unversioned_database(name: &str) -> (PathBuf, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("fm-{}-{}", name, uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("filemanager.db");
+        Connection::open(&path).unwrap().execute_batch(UNVERSIONED_SCHEMA).unwrap();
+        (dir, path)
+    }
+
+    fn has_column(conn: &Connection, table: &str, column: &str) -> bool {
+        conn.prepare(&format!("SELECT 1 FROM pragma_table_info('{}') WHERE name = ?1", table))
+            .unwrap()
+            .exists([column])
+            .unwrap()
+    }
+
+    #[test]
+    fn test_upgrade_unversioned_database() {
+        let (dir, path) = unversioned_database("migrate");
+
+        let db = Database::new(path.to_str().unwrap()).unwrap();
+
+        // Existing rows survive, and the new tables are usable
+        let logs = db.get_recent_logs(10).unwrap();
+        assert_eq!(logs.len(), 1);
+        assert!(!logs[0].undone);
+        assert_eq!(db.get_setting::<String>("trash_backend").unwrap(), None);
+        db.set_setting("trash_backend", &"app").unwrap();
+        assert!(db.get_allowed_roots().unwrap().is_empty());
+        assert_eq!(db.verify_log_integrity().unwrap().first_broken_id, None);
+        drop(db);
+
+        let conn = Connection::open(&path).unwrap();
+        assert_eq!(current_version(&conn).unwrap(), latest_version());
+        assert!(has_column(&conn, "action_logs", "undone"));
+        assert!(has_column(&conn, "file_entries", "is_symlink"));
+
+        // The backup is the database as it was before the upgrade
+        let backup = Connection::open(backup_path(&path, 0)).unwrap();
+        assert_eq!(current_version(&backup).unwrap(), 0);
+        let rows: i64 = backup.query_row("SELECT COUNT(*) FROM action_logs", [], |row| row.get(0)).unwrap();
+        assert_eq!(rows, 1);
+        assert!(!has_column(&backup, "action_logs", "undone"));
+
+        // Opening an up-to-date database again changes nothing
+        std::fs::remove_file(backup_path(&path, 0)).unwrap();
+        drop(Database::new(path.to_str().unwrap()).unwrap());
+        assert!(!backup_path(&path, 0).exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_failed_migration_rolls_back() {
+        let (dir, path) = unversioned_database("rollback");
+        let mut migrations = MIGRATIONS.to_vec();
+        migrations.push(Migration {
+            version: latest_version() + 1,
+            description: "broken",
+            up: |tx| Ok(tx.execute_batch("ALTER TABLE missing ADD COLUMN x TEXT")?),
+        });
+
+        let mut conn = Connection::open(&path).unwrap();
+        assert!(matches!(run_migrations(&mut conn, &migrations), Err(AppError::DatabaseError(_))));
+
+        // None of the earlier migrations stuck either
+        assert_eq!(current_version(&conn).unwrap(), 0);
+        assert!(!has_column(&conn, "action_logs", "undone"));
+        let rows: i64 = conn.query_row("SELECT COUNT(*) FROM action_logs", [], |row| row.get(0)).unwrap();
+        assert_eq!(rows, 1);
+
+        // The backup taken before the attempt stays
+        let backup = Connection::open(backup_path(&path, 0)).unwrap();
+        let rows: i64 = backup.query_row("SELECT COUNT(*) FROM action_logs", [], |row| row.get(0)).unwrap();
+        assert_eq!(rows, 1);
+
+        drop((conn, backup));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_newer_database_is_rejected() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn).unwrap();
+        conn.execute(
+            "INSERT INTO schema_version (version, description, applied_at) VALUES (?1, 'future', '')",
+            [latest_version() + 1],
+        )
+        .unwrap();
+
+        assert!(matches!(migrate(&mut conn), Err(AppError::DatabaseError(_))));
+    }
+}
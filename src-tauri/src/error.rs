@@ -30,6 +30,21 @@ pub enum AppError {
     #[error("Undo failed: {0}")]
     UndoFailed(String),
 
+    #[error("Invalid query: {0}")]
+    InvalidQuery(String),
+
+    #[error("Operation cancelled: {0}")]
+    Cancelled(String),
+
+    #[error("Not found: {0}")]
+    NotFound(String),
+
+    #[error("Invalid state: {0}")]
+    InvalidState(String),
+
+    #[error("Invalid data: {0}")]
+    InvalidData(String),
+
     #[error("Unknown error: {0}")]
     Unknown(String),
 }
@@ -46,12 +61,24 @@ impl From<rusqlite::Error> for AppError {
     }
 }
 
+impl From<r2d2::Error> for AppError {
+    fn from(error: r2d2::Error) -> Self {
+        AppError::DatabaseError(error.to_string())
+    }
+}
+
 impl From<serde_json::Error> for AppError {
     fn from(error: serde_json::Error) -> Self {
         AppError::IoError(error.to_string())
     }
 }
 
+impl From<csv::Error> for AppError {
+    fn from(error: csv::Error) -> Self {
+        AppError::IoError(error.to_string())
+    }
+}
+
 impl serde::Serialize for AppError {
     fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
     where
@@ -66,7 +93,7 @@ impl serde::Serialize for AppError {
 }
 
 impl AppError {
-    fn error_code(&self) -> &str {
+    pub fn error_code(&self) -> &'static str {
         match self {
             AppError::FileNotFound(_) => "FILE_NOT_FOUND",
             AppError::PermissionDenied(_) => "PERMISSION_DENIED",
@@ -77,6 +104,11 @@ impl AppError {
             AppError::DatabaseError(_) => "DATABASE_ERROR",
             AppError::NotAllowed(_) => "NOT_ALLOWED",
             AppError::UndoFailed(_) => "UNDO_FAILED",
+            AppError::InvalidQuery(_) => "INVALID_QUERY",
+            AppError::Cancelled(_) => "CANCELLED",
+            AppError::NotFound(_) => "NOT_FOUND",
+            AppError::InvalidState(_) => "INVALID_STATE",
+            AppError::InvalidData(_) => "INVALID_DATA",
             AppError::Unknown(_) => "UNKNOWN_ERROR",
         }
     }
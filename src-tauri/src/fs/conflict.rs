@@ -0,0 +1,160 @@
+use crate::error::{AppError, Result};
+use crate::fs::sandbox::Location;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// What to do when the destination of an operation already exists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictPolicy {
+    Skip,
+    Overwrite,
+    KeepBoth,
+    #[default]
+    Fail,
+}
+
+/// How a single item's destination was actually settled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictResolution {
+    NoConflict,
+    Skipped,
+    Overwritten,
+    Renamed,
+    Failed,
+}
+
+impl ConflictResolution {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConflictResolution::NoConflict => "no_conflict",
+            ConflictResolution::Skipped => "skipped",
+            ConflictResolution::Overwritten => "overwritten",
+            ConflictResolution::Renamed => "renamed",
+            ConflictResolution::Failed => "failed",
+        }
+    }
+
+    /// Message stored alongside the log row, so the history shows how a
+    /// conflict was settled. `None` when there was nothing to settle.
+    pub fn log_message(&self) -> Option<String> {
+        match self {
+            ConflictResolution::NoConflict => None,
+            other => Some(format!("Conflict resolved: {}", other.as_str())),
+        }
+    }
+}
+
+/// Destination chosen for an item after applying a `ConflictPolicy`.
+pub enum Destination {
+    /// Go ahead and write to this location.
+    Use(Location, ConflictResolution),
+    /// Write to this location with `replace`, which sets the existing entry
+    /// aside first.
+    Replace(Location),
+    /// Leave the item alone.
+    Skip,
+    /// Refuse the item with this error.
+    Fail(AppError),
+}
+
+/// Where `Overwrite` puts the entry it replaces, so that nothing is lost if
+/// the write fails and the replaced entry can be brought back by an undo.
+pub trait SetAside {
+    /// Moves `entry` out of the way and returns where it went.
+    fn set_aside(&self, entry: &Location) -> Result<PathBuf>;
+
+    /// Moves an entry set aside at `set_aside` back to `entry`.
+    fn put_back(&self, set_aside: &Path, entry: &Location) -> Result<()>;
+}
+
+/// Applies `policy` to `dest`. Nothing is removed here; an `Overwrite`
+/// conflict comes back as `Destination::Replace`.
+pub fn resolve_destination(dest: Location, policy: ConflictPolicy) -> Result<Destination> {
+    if !dest.exists() {
+        return Ok(Destination::Use(dest, ConflictResolution::NoConflict));
+    }
+
+    match policy {
+        ConflictPolicy::Skip => Ok(Destination::Skip),
+        ConflictPolicy::Fail => Ok(Destination::Fail(AppError::FileExists(dest.path().to_string_lossy().to_string()))),
+        ConflictPolicy::Overwrite => Ok(Destination::Replace(dest)),
+        ConflictPolicy::KeepBoth => Ok(Destination::Use(
+            keep_both(&dest)?,
+            ConflictResolution::Renamed,
+        )),
+    }
+}
+
+/// Sets the entry at `target` aside with `aside` and runs `write`, which
+/// writes to `target`. If the write fails the entry is put back. Returns
+/// where the replaced entry went.
+pub fn replace(target: &Location, aside: &dyn SetAside, write: impl FnOnce() -> Result<()>) -> Result<PathBuf> {
+    let set_aside = aside.set_aside(target)?;
+    if let Err(e) = write() {
+        let _ = aside.put_back(&set_aside, target);
+        return Err(e);
+    }
+    Ok(set_aside)
+}
+
+/// First free sibling of `dest` named "name (2).ext", "name (3).ext", ...
+pub fn keep_both(dest: &Location) -> Result<Location> {
+    let path = dest.path();
+    let mut n = 2;
+    loop {
+        let candidate = numbered_path(&path, n);
+        let candidate = dest.with_file_name(candidate.file_name().unwrap_or_default())?;
+        if !candidate.exists() {
+            return Ok(candidate);
+        }
+        n += 1;
+    }
+}
+
+/// Sibling of `path` named "name (n).ext"; `n <= 1` gives `path` itself.
+pub fn numbered_path(path: &Path, n: usize) -> PathBuf {
+    if n <= 1 {
+        return path.to_path_buf();
+    }
+
+    let parent = path.parent().unwrap_or_else(|| Path::new(""));
+    let file_name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    // Dotfiles like ".bashrc" have no extension to preserve
+    let (stem, ext) = match file_name.rfind('.') {
+        Some(idx) if idx > 0 => (&file_name[..idx], &file_name[idx..]),
+        _ => (file_name.as_str(), ""),
+    };
+
+    parent.join(format!("{} ({}){}", stem, n, ext))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::sandbox::{AllowedRoot, RootMode, Sandbox};
+    use std::fs;
+
+    #[test]
+    fn test_keep_both() {
+        let dir = std::env::temp_dir().join(format!("fm-conflict-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let dir = dir.canonicalize().unwrap();
+        let sandbox = Sandbox::new(&[AllowedRoot { path: dir.to_string_lossy().to_string(), mode: RootMode::ReadWrite }], false);
+        let keep_both_path = |path: PathBuf| keep_both(&sandbox.locate(&path).unwrap()).unwrap().path();
+
+        assert_eq!(keep_both_path(dir.join("report.pdf")), dir.join("report (2).pdf"));
+        assert_eq!(keep_both_path(dir.join(".bashrc")), dir.join(".bashrc (2)"));
+        assert_eq!(keep_both_path(dir.join("archive.tar.gz")), dir.join("archive.tar (2).gz"));
+
+        fs::write(dir.join("report (2).pdf"), "").unwrap();
+        assert_eq!(keep_both_path(dir.join("report.pdf")), dir.join("report (3).pdf"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,258 @@
+use crate::error::{AppError, Result};
+use crate::fs::operations::Progress;
+use crate::fs::sandbox::Location;
+use encoding_rs::{Encoding, UTF_8, WINDOWS_1252};
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use std::io::Read;
+
+/// Files larger than this are skipped unless the caller sets its own limit.
+const DEFAULT_MAX_FILE_SIZE: u64 = 10 * 1024 * 1024;
+
+/// Matches returned at most unless the caller sets its own limit.
+const DEFAULT_MAX_RESULTS: usize = 1000;
+
+/// Bytes inspected when deciding whether a file is binary.
+const SNIFF_LEN: usize = 8192;
+
+/// Longest snippet returned for a hit, in characters.
+const SNIPPET_LEN: usize = 200;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ContentSearchOptions {
+    /// Treat the query as a regular expression instead of literal text
+    pub regex: bool,
+    pub case_sensitive: bool,
+    pub max_depth: Option<usize>,
+    pub max_file_size: Option<u64>,
+    pub max_results: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContentMatch {
+    pub path: String,
+    /// 1-based
+    pub line: usize,
+    /// 1-based, in characters
+    pub column: usize,
+    pub snippet: String,
+    pub encoding: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ContentSearchResult {
+    pub matches: Vec<ContentMatch>,
+    pub files_searched: usize,
+    /// Binary, oversized or unreadable files
+    pub files_skipped: usize,
+    /// More matches existed than `max_results`
+    pub truncated: bool,
+    pub cancelled: bool,
+}
+
+pub struct ContentSearcher {
+    pattern: Regex,
+    options: ContentSearchOptions,
+}
+
+impl ContentSearcher {
+    pub fn new(query: &str, options: ContentSearchOptions) -> Result<Self> {
+        if query.is_empty() {
+            return Err(AppError::InvalidQuery("Query is empty".to_string()));
+        }
+
+        let source = if options.regex { query.to_string() } else { regex::escape(query) };
+        let pattern = RegexBuilder::new(&source)
+            .case_insensitive(!options.case_sensitive)
+            .build()
+            .map_err(|e| AppError::InvalidQuery(e.to_string()))?;
+
+        Ok(ContentSearcher { pattern, options })
+    }
+
+    /// Searches every text file under `root`, reading them through its
+    /// handle and without following symlinks.
+    pub fn search(&self, root: &Location, progress: &dyn Progress) -> ContentSearchResult {
+        let max_file_size = self.options.max_file_size.unwrap_or(DEFAULT_MAX_FILE_SIZE);
+        let max_results = self.options.max_results.unwrap_or(DEFAULT_MAX_RESULTS);
+
+        let mut result = ContentSearchResult::default();
+
+        for (entry, file_type) in root.walk(self.options.max_depth) {
+            if progress.is_cancelled() {
+                result.cancelled = true;
+                break;
+            }
+            if !file_type.is_file() {
+                continue;
+            }
+            let path = entry.path();
+            progress.item_started(&path);
+
+            let bytes = read_file(&entry, max_file_size);
+            progress.bytes_done(bytes.as_ref().map_or(0, |b| b.len() as u64));
+            let text = bytes.and_then(|bytes| decode_text(&bytes));
+
+            let (text, encoding) = match text {
+                Some(text) => text,
+                None => {
+                    result.files_skipped += 1;
+                    continue;
+                }
+            };
+            result.files_searched += 1;
+
+            for (index, line) in text.lines().enumerate() {
+                if let Some(found) = self.pattern.find(line) {
+                    if result.matches.len() >= max_results {
+                        result.truncated = true;
+                        return result;
+                    }
+                    result.matches.push(ContentMatch {
+                        path: path.to_string_lossy().to_string(),
+                        line: index + 1,
+                        column: line[..found.start()].chars().count() + 1,
+                        snippet: snippet(line, found.start()),
+                        encoding: encoding.name().to_string(),
+                    });
+                }
+            }
+        }
+
+        result
+    }
+}
+
+/// Contents of a regular file of at most `max_size` bytes. The file is
+/// checked through the handle it is read from, and never read past the
+/// limit, so one that was swapped or grew since the walk is skipped.
+fn read_file(location: &Location, max_size: u64) -> Option<Vec<u8>> {
+    let file = location.open_nonblocking().ok()?;
+    let metadata = file.metadata().ok()?;
+    if !metadata.is_file() || metadata.len() > max_size {
+        return None;
+    }
+
+    let mut bytes = Vec::new();
+    file.take(max_size + 1).read_to_end(&mut bytes).ok()?;
+    (bytes.len() as u64 <= max_size).then_some(bytes)
+}
+
+/// Decodes a file as text, or `None` if it looks binary. UTF-16 and UTF-8
+/// are recognised by their byte order mark; without one the file is read as
+/// UTF-8 if valid and as Windows-1252 otherwise.
+fn decode_text(bytes: &[u8]) -> Option<(String, &'static Encoding)> {
+    if let Some((encoding, bom_len)) = Encoding::for_bom(bytes) {
+        let (text, _) = encoding.decode_without_bom_handling(&bytes[bom_len..]);
+        return Some((text.into_owned(), encoding));
+    }
+
+    // NUL bytes practically never occur in 8-bit text
+    if bytes[..bytes.len().min(SNIFF_LEN)].contains(&0) {
+        return None;
+    }
+
+    let encoding = if std::str::from_utf8(bytes).is_ok() { UTF_8 } else { WINDOWS_1252 };
+    let (text, _) = encoding.decode_without_bom_handling(bytes);
+    Some((text.into_owned(), encoding))
+}
+
+/// The part of `line` around byte offset `start`, at most `SNIPPET_LEN` characters.
+fn snippet(line: &str, start: usize) -> String {
+    let line = line.trim_end();
+    if line.chars().count() <= SNIPPET_LEN {
+        return line.to_string();
+    }
+
+    // Keep some context before the match
+    let skip = line[..start.min(line.len())].chars().count().saturating_sub(SNIPPET_LEN / 4);
+    line.chars().skip(skip).take(SNIPPET_LEN).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::operations::NoProgress;
+    use crate::fs::sandbox::{AllowedRoot, RootMode, Sandbox};
+    use std::fs;
+
+    #[test]
+    fn test_search_contents() {
+        let root = std::env::temp_dir().join(format!("fm-grep-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::write(root.join("src/main.rs"), "fn main() {\n    println!(\"TODO: tidy\");\n}\n").unwrap();
+        fs::write(root.join("latin1.txt"), b"caf\xe9 todo\n").unwrap();
+        fs::write(root.join("utf16.txt"), [0xFF, 0xFE, b'T', 0, b'O', 0, b'D', 0, b'O', 0]).unwrap();
+        fs::write(root.join("blob.bin"), [b'T', b'O', b'D', b'O', 0, 1, 2]).unwrap();
+
+        let searcher = ContentSearcher::new("todo", ContentSearchOptions::default()).unwrap();
+        let mut result = searcher.search(&Location::ambient(&root).unwrap(), &NoProgress);
+        result.matches.sort_by(|a, b| a.path.cmp(&b.path));
+
+        assert_eq!(result.files_searched, 3);
+        assert_eq!(result.files_skipped, 1);
+        let found: Vec<(&str, usize, usize, &str)> = result
+            .matches
+            .iter()
+            .map(|m| (m.snippet.as_str(), m.line, m.column, m.encoding.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("café todo", 1, 6, "windows-1252"),
+                ("    println!(\"TODO: tidy\");", 2, 15, "UTF-8"),
+                ("TODO", 1, 1, "UTF-16LE"),
+            ]
+        );
+
+        let regex = ContentSearchOptions { regex: true, case_sensitive: true, ..Default::default() };
+        let result = ContentSearcher::new(r"fn \w+\(", regex).unwrap().search(&Location::ambient(&root).unwrap(), &NoProgress);
+        assert_eq!(result.matches.len(), 1);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_read_file_limits() {
+        let root = std::env::temp_dir().join(format!("fm-grep-read-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("small.txt"), "todo").unwrap();
+        fs::write(root.join("big.txt"), "todo todo").unwrap();
+        let fifo = std::ffi::CString::new(root.join("fifo").to_str().unwrap()).unwrap();
+        assert_eq!(unsafe { libc::mkfifo(fifo.as_ptr(), 0o644) }, 0);
+
+        let read = |name: &str| read_file(&Location::ambient(&root.join(name)).unwrap(), 4);
+        assert_eq!(read("small.txt").as_deref(), Some(&b"todo"[..]));
+        assert_eq!(read("big.txt"), None);
+        // Passed over without waiting for a writer
+        assert_eq!(read("fifo"), None);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_links_out_of_the_root_are_not_searched() {
+        let base = std::env::temp_dir().join(format!("fm-grep-links-{}", uuid::Uuid::new_v4()));
+        let (root, outside) = (base.join("root"), base.join("outside"));
+        fs::create_dir_all(&root).unwrap();
+        fs::create_dir_all(&outside).unwrap();
+        fs::write(root.join("notes.txt"), "todo").unwrap();
+        fs::write(outside.join("secret.txt"), "todo").unwrap();
+        std::os::unix::fs::symlink(&outside, root.join("out")).unwrap();
+        std::os::unix::fs::symlink(outside.join("secret.txt"), root.join("secret.txt")).unwrap();
+
+        let sandbox = Sandbox::new(&[AllowedRoot { path: root.to_string_lossy().to_string(), mode: RootMode::ReadOnly }], false);
+        let searcher = ContentSearcher::new("todo", ContentSearchOptions::default()).unwrap();
+        let result = searcher.search(&sandbox.locate(&root).unwrap(), &NoProgress);
+        let paths: Vec<&str> = result.matches.iter().map(|m| m.path.as_str()).collect();
+        assert_eq!(paths, vec![root.canonicalize().unwrap().join("notes.txt").to_str().unwrap()]);
+
+        // Nor can a search start at a link that leads out
+        assert!(matches!(sandbox.locate(&root.join("out")).unwrap().follow(), Err(AppError::NotAllowed(_))));
+
+        fs::remove_dir_all(&base).unwrap();
+    }
+}
@@ -1,11 +1,20 @@
+pub mod conflict;
+pub mod content_search;
 pub mod operations;
+pub mod sandbox;
+pub mod search;
 pub mod trash;
 pub mod validators;
 
 use crate::error::{AppError, Result};
+use cap_std::fs::Metadata;
+use sandbox::Location;
 use serde::{Deserialize, Serialize};
-use std::fs;
-use std::path::{Path, PathBuf};
+use std::io::Read;
+use std::path::PathBuf;
+
+/// Bytes read from the start of a file to sniff its MIME type.
+const MIME_SNIFF_LEN: usize = 8192;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FileItem {
@@ -13,6 +22,8 @@ pub struct FileItem {
     pub path: String,
     #[serde(rename = "isDir")]
     pub is_dir: bool,
+    #[serde(rename = "isSymlink")]
+    pub is_symlink: bool,
     pub size: u64,
     pub modified: String,
     pub ext: Option<String>,
@@ -27,11 +38,31 @@ pub struct FileStat {
     pub size: u64,
     pub modified: String,
     pub created: String,
+    pub accessed: String,
+    #[serde(rename = "isSymlink")]
+    pub is_symlink: bool,
+    /// Where a symlink points, as stored in the link
+    pub symlink_target: Option<String>,
+    /// `ls -l` style, e.g. `drwxr-xr-x`; Unix only
+    pub permissions: Option<String>,
+    /// Permission bits in octal, e.g. `0755`; Unix only
+    pub mode: Option<String>,
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+    pub owner: Option<String>,
+    pub group: Option<String>,
+    pub inode: Option<u64>,
+    pub nlink: Option<u64>,
+    /// Sniffed from the content; `None` if it couldn't be read
+    pub mime_type: Option<String>,
 }
 
 impl FileItem {
-    pub fn from_path(path: &Path) -> Result<Self> {
-        let metadata = fs::metadata(path)?;
+    /// Describes what the entry resolves to; a broken symlink, or one that
+    /// leads out of the root, is described as the link itself.
+    pub fn from_location(location: &Location) -> Result<Self> {
+        let (metadata, is_symlink) = resolved_metadata(location)?;
+        let path = location.path();
         let name = path
             .file_name()
             .ok_or_else(|| AppError::InvalidPath("Invalid file name".to_string()))?
@@ -45,77 +76,167 @@ impl FileItem {
             None
         };
 
-        let modified = metadata
-            .modified()
-            .map(|t| {
-                let datetime: chrono::DateTime<chrono::Utc> = t.into();
-                datetime.to_rfc3339()
-            })
-            .unwrap_or_default();
-
         Ok(FileItem {
             name,
             path: path.to_string_lossy().to_string(),
             is_dir: metadata.is_dir(),
+            is_symlink,
             size: metadata.len(),
-            modified,
+            modified: format_time(metadata.modified()),
             ext,
         })
     }
 }
 
 impl FileStat {
-    pub fn from_path(path: &Path) -> Result<Self> {
-        let metadata = fs::metadata(path)?;
+    /// Describes what the entry resolves to, as `FileItem::from_location` does.
+    pub fn from_location(location: &Location) -> Result<Self> {
+        let (metadata, is_symlink) = resolved_metadata(location)?;
+        let path = location.path();
         let name = path
             .file_name()
             .ok_or_else(|| AppError::InvalidPath("Invalid file name".to_string()))?
             .to_string_lossy()
             .to_string();
 
-        let modified = metadata
-            .modified()
-            .map(|t| {
-                let datetime: chrono::DateTime<chrono::Utc> = t.into();
-                datetime.to_rfc3339()
-            })
-            .unwrap_or_default();
-
-        let created = metadata
-            .created()
-            .map(|t| {
-                let datetime: chrono::DateTime<chrono::Utc> = t.into();
-                datetime.to_rfc3339()
-            })
-            .unwrap_or_default();
-
-        Ok(FileStat {
+        let symlink_target = if is_symlink {
+            location.read_link().ok().map(|t| t.to_string_lossy().to_string())
+        } else {
+            None
+        };
+
+        #[cfg_attr(not(unix), allow(unused_mut))]
+        let mut stat = FileStat {
             name,
             path: path.to_string_lossy().to_string(),
             is_dir: metadata.is_dir(),
             size: metadata.len(),
-            modified,
-            created,
-        })
+            modified: format_time(metadata.modified()),
+            created: format_time(metadata.created()),
+            accessed: format_time(metadata.accessed()),
+            is_symlink,
+            symlink_target,
+            permissions: None,
+            mode: None,
+            uid: None,
+            gid: None,
+            owner: None,
+            group: None,
+            inode: None,
+            nlink: None,
+            mime_type: sniff_mime_type(location, &metadata),
+        };
+
+        #[cfg(unix)]
+        {
+            use cap_std::fs::MetadataExt;
+
+            stat.permissions = Some(permission_string(&metadata));
+            stat.mode = Some(format!("{:04o}", metadata.mode() & 0o7777));
+            stat.uid = Some(metadata.uid());
+            stat.gid = Some(metadata.gid());
+            stat.owner = uzers::get_user_by_uid(metadata.uid()).map(|u| u.name().to_string_lossy().to_string());
+            stat.group = uzers::get_group_by_gid(metadata.gid()).map(|g| g.name().to_string_lossy().to_string());
+            stat.inode = Some(metadata.ino());
+            stat.nlink = Some(metadata.nlink());
+        }
+
+        Ok(stat)
     }
 }
 
-pub fn normalize_path(path: &str) -> PathBuf {
-    PathBuf::from(path.replace('\\', "/"))
+/// Metadata of what `location` resolves to, or of the link itself if it
+/// can't be followed, and whether `location` is a symlink.
+fn resolved_metadata(location: &Location) -> Result<(Metadata, bool)> {
+    let link_metadata = location.symlink_metadata()?;
+    if !link_metadata.file_type().is_symlink() {
+        return Ok((link_metadata, false));
+    }
+    Ok((location.metadata().unwrap_or(link_metadata), true))
+}
+
+fn format_time(time: std::io::Result<cap_std::time::SystemTime>) -> String {
+    time.map(|t| {
+        let datetime: chrono::DateTime<chrono::Utc> = t.into_std().into();
+        datetime.to_rfc3339()
+    })
+    .unwrap_or_default()
 }
 
-pub fn is_path_safe(path: &Path, allowed_roots: &[PathBuf]) -> bool {
-    let canonical = match path.canonicalize() {
-        Ok(p) => p,
-        Err(_) => return false,
+/// File type character followed by the rwx triplets, as `ls -l` prints them.
+#[cfg(unix)]
+fn permission_string(metadata: &Metadata) -> String {
+    use cap_std::fs::{FileTypeExt, PermissionsExt};
+
+    let file_type = metadata.file_type();
+    let kind = if file_type.is_dir() {
+        'd'
+    } else if file_type.is_symlink() {
+        'l'
+    } else if file_type.is_block_device() {
+        'b'
+    } else if file_type.is_char_device() {
+        'c'
+    } else if file_type.is_fifo() {
+        'p'
+    } else if file_type.is_socket() {
+        's'
+    } else {
+        '-'
     };
 
-    for root in allowed_roots {
-        if let Ok(canonical_root) = root.canonicalize() {
-            if canonical.starts_with(&canonical_root) {
-                return true;
-            }
-        }
+    let mode = metadata.permissions().mode();
+    let mut out = String::with_capacity(10);
+    out.push(kind);
+    for (shift, special, special_char) in [(6, 0o4000, 's'), (3, 0o2000, 's'), (0, 0o1000, 't')] {
+        let bits = (mode >> shift) & 0o7;
+        out.push(if bits & 0o4 != 0 { 'r' } else { '-' });
+        out.push(if bits & 0o2 != 0 { 'w' } else { '-' });
+        out.push(match (bits & 0o1 != 0, mode & special != 0) {
+            (true, true) => special_char,
+            (false, true) => special_char.to_ascii_uppercase(),
+            (true, false) => 'x',
+            (false, false) => '-',
+        });
+    }
+    out
+}
+
+/// MIME type from the file's leading bytes. Files without a known signature
+/// are `text/plain` if they look like text. Only regular files are read, so
+/// a FIFO can't block the call.
+fn sniff_mime_type(location: &Location, metadata: &Metadata) -> Option<String> {
+    if metadata.is_dir() {
+        return Some("inode/directory".to_string());
+    }
+    if metadata.file_type().is_symlink() {
+        // Only reached for a broken link
+        return Some("inode/symlink".to_string());
+    }
+    if !metadata.is_file() {
+        return None;
+    }
+    if metadata.len() == 0 {
+        return Some("application/x-empty".to_string());
     }
-    false
-}
\ No newline at end of file
+
+    let mut head = Vec::with_capacity(MIME_SNIFF_LEN);
+    location
+        .open()
+        .ok()?
+        .into_std()
+        .take(MIME_SNIFF_LEN as u64)
+        .read_to_end(&mut head)
+        .ok()?;
+
+    let mime = match infer::get(&head) {
+        Some(kind) => kind.mime_type(),
+        None if !head.contains(&0) => "text/plain",
+        None => "application/octet-stream",
+    };
+    Some(mime.to_string())
+}
+
+pub fn normalize_path(path: &str) -> PathBuf {
+    PathBuf::from(path.replace('\\', "/"))
+}
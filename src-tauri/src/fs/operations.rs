@@ -1,6 +1,9 @@
 use crate::error::{AppError, Result};
-use crate::fs::{FileItem, FileStat};
+use crate::fs::conflict::{replace, resolve_destination, ConflictPolicy, ConflictResolution, Destination, SetAside};
+use crate::fs::sandbox::{Location, Sandbox};
+use crate::fs::{validators, FileItem, FileStat};
 use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
 use std::fs;
 use std::path::{Path, PathBuf};
 use uuid::Uuid;
@@ -25,7 +28,11 @@ pub struct BatchResult {
     pub success: bool,
     pub processed: usize,
     pub failed: usize,
+    pub skipped: usize,
+    pub cancelled: bool,
     pub batch_id: String,
+    /// Job the batch ran as, for commands that run one
+    pub job_id: Option<String>,
     pub results: Vec<BatchItemResult>,
 }
 
@@ -34,6 +41,95 @@ pub struct BatchItemResult {
     pub path: String,
     pub success: bool,
     pub message: Option<String>,
+    pub dest_path: Option<String>,
+    pub resolution: ConflictResolution,
+    /// `AppError` code of a failed item
+    pub error_code: Option<String>,
+    /// Where the entry an overwrite replaced was set aside
+    pub replaced_path: Option<String>,
+}
+
+impl BatchResult {
+    pub fn from_results(batch_id: String, results: Vec<BatchItemResult>) -> Self {
+        let failed = results.iter().filter(|r| !r.success).count();
+        let skipped = results
+            .iter()
+            .filter(|r| r.resolution == ConflictResolution::Skipped)
+            .count();
+
+        BatchResult {
+            success: failed == 0,
+            processed: results.len() - failed - skipped,
+            failed,
+            skipped,
+            cancelled: false,
+            batch_id,
+            job_id: None,
+            results,
+        }
+    }
+}
+
+impl BatchItemResult {
+    pub fn done(path: &Path, dest: &Path, resolution: ConflictResolution) -> Self {
+        BatchItemResult {
+            path: path.to_string_lossy().to_string(),
+            success: true,
+            message: resolution.log_message(),
+            dest_path: Some(dest.to_string_lossy().to_string()),
+            resolution,
+            error_code: None,
+            replaced_path: None,
+        }
+    }
+
+    /// An overwrite, with the replaced entry now at `replaced`.
+    pub fn replaced(path: &Path, dest: &Path, replaced: &Path) -> Self {
+        BatchItemResult {
+            replaced_path: Some(replaced.to_string_lossy().to_string()),
+            ..Self::done(path, dest, ConflictResolution::Overwritten)
+        }
+    }
+
+    pub fn skipped(path: &Path) -> Self {
+        Self::skipped_with(path, "Skipped: destination already exists")
+    }
+
+    pub fn skipped_with(path: &Path, message: impl Into<String>) -> Self {
+        BatchItemResult {
+            path: path.to_string_lossy().to_string(),
+            success: true,
+            message: Some(message.into()),
+            dest_path: None,
+            resolution: ConflictResolution::Skipped,
+            error_code: None,
+            replaced_path: None,
+        }
+    }
+
+    pub fn failed(path: &Path, error: AppError) -> Self {
+        BatchItemResult {
+            path: path.to_string_lossy().to_string(),
+            success: false,
+            message: Some(error.to_string()),
+            dest_path: None,
+            resolution: ConflictResolution::Failed,
+            error_code: Some(error.error_code().to_string()),
+            replaced_path: None,
+        }
+    }
+
+    pub fn cancelled(path: &Path) -> Self {
+        BatchItemResult {
+            message: Some("Cancelled".to_string()),
+            ..Self::failed(path, AppError::Cancelled(path.to_string_lossy().to_string()))
+        }
+    }
+
+    /// Whether the item was actually written somewhere and should be logged.
+    pub fn was_applied(&self) -> bool {
+        self.success && self.resolution != ConflictResolution::Skipped
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -42,172 +138,776 @@ pub struct UndoResult {
     pub action: String,
     pub items_restored: usize,
     pub message: Option<String>,
+    pub items: Vec<UndoItemResult>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UndoItemResult {
+    pub log_id: Option<i64>,
+    pub action: String,
+    pub path: Option<String>,
+    pub success: bool,
+    pub message: Option<String>,
 }
 
-pub fn list_directory(path: &Path) -> Result<Vec<FileItem>> {
-    if !path.exists() {
-        return Err(AppError::FileNotFound(path.to_string_lossy().to_string()));
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortKey {
+    /// Natural order, so "file2" comes before "file10"
+    #[default]
+    Name,
+    Size,
+    Modified,
+    Ext,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortDirection {
+    #[default]
+    Asc,
+    Desc,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ListOptions {
+    pub sort_by: SortKey,
+    pub direction: SortDirection,
+    /// Directories before files, whatever the direction
+    pub dirs_first: bool,
+    pub show_hidden: bool,
+    pub offset: usize,
+    /// `None` returns everything from `offset` on
+    pub limit: Option<usize>,
+}
+
+impl Default for ListOptions {
+    fn default() -> Self {
+        ListOptions {
+            sort_by: SortKey::default(),
+            direction: SortDirection::default(),
+            dirs_first: true,
+            show_hidden: true,
+            offset: 0,
+            limit: None,
+        }
     }
+}
 
-    if !path.is_dir() {
+/// One page of a sorted directory listing.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DirPage {
+    pub items: Vec<FileItem>,
+    /// Entries in the whole listing, after hidden-file filtering
+    pub total: usize,
+    pub offset: usize,
+    /// Offset of the next page, `None` on the last one
+    pub next_offset: Option<usize>,
+}
+
+struct ListEntry {
+    location: Location,
+    name: String,
+    is_dir: bool,
+    /// Only read up front when sorting needs it
+    item: Option<FileItem>,
+}
+
+impl ListEntry {
+    fn ext(&self) -> Option<String> {
+        if self.is_dir {
+            return None;
+        }
+        Path::new(&self.name).extension().map(|e| e.to_string_lossy().to_lowercase())
+    }
+}
+
+/// Lists a directory sorted and paged as `options` asks. Entries are only
+/// stat'ed for the returned page unless sorting by size or date, which keeps
+/// name-sorted pages of huge directories cheap.
+pub fn list_directory(dir: &Location, options: &ListOptions) -> Result<DirPage> {
+    if !dir.exists() {
+        return Err(AppError::FileNotFound(dir.path().to_string_lossy().to_string()));
+    }
+
+    if !dir.metadata()?.is_dir() {
         return Err(AppError::InvalidPath("Not a directory".to_string()));
     }
 
-    let mut items = Vec::new();
-    let entries = fs::read_dir(path)?;
+    let needs_stat = matches!(options.sort_by, SortKey::Size | SortKey::Modified);
+    let mut entries = Vec::new();
 
-    for entry in entries {
+    for entry in dir.read_dir()? {
         let entry = entry?;
-        let path = entry.path();
-        
-        if let Ok(item) = FileItem::from_path(&path) {
-            items.push(item);
+        let location = dir.join(entry.file_name());
+
+        if !options.show_hidden && validators::is_hidden_file(&location.path()) {
+            continue;
         }
+
+        let file_type = entry.file_type()?;
+        let item = if needs_stat {
+            match FileItem::from_location(&location) {
+                Ok(item) => Some(item),
+                Err(_) => continue,
+            }
+        } else {
+            None
+        };
+
+        let is_link_to_dir = file_type.is_symlink() && location.metadata().is_ok_and(|m| m.is_dir());
+        entries.push(ListEntry {
+            is_dir: file_type.is_dir() || is_link_to_dir,
+            name: entry.file_name().to_string_lossy().to_string(),
+            location,
+            item,
+        });
     }
 
-    Ok(items)
+    entries.sort_by(|a, b| {
+        let by_kind = if options.dirs_first { b.is_dir.cmp(&a.is_dir) } else { Ordering::Equal };
+        let by_key = match options.sort_by {
+            SortKey::Name => Ordering::Equal,
+            SortKey::Ext => a.ext().cmp(&b.ext()),
+            SortKey::Size => a.item.as_ref().map(|i| i.size).cmp(&b.item.as_ref().map(|i| i.size)),
+            SortKey::Modified => a
+                .item
+                .as_ref()
+                .map(|i| &i.modified)
+                .cmp(&b.item.as_ref().map(|i| &i.modified)),
+        }
+        .then_with(|| natord::compare_ignore_case(&a.name, &b.name));
+
+        let by_key = match options.direction {
+            SortDirection::Asc => by_key,
+            SortDirection::Desc => by_key.reverse(),
+        };
+        by_kind.then(by_key)
+    });
+
+    let total = entries.len();
+    let start = options.offset.min(total);
+    let end = options.limit.map_or(total, |limit| start.saturating_add(limit).min(total));
+
+    let items = entries
+        .drain(start..end)
+        .filter_map(|entry| entry.item.or_else(|| FileItem::from_location(&entry.location).ok()))
+        .collect();
+
+    Ok(DirPage {
+        items,
+        total,
+        offset: start,
+        next_offset: (end < total).then_some(end),
+    })
 }
 
-pub fn get_file_stat(path: &Path) -> Result<FileStat> {
-    if !path.exists() {
-        return Err(AppError::FileNotFound(path.to_string_lossy().to_string()));
+pub fn get_file_stat(location: &Location) -> Result<FileStat> {
+    // exists() doesn't follow links, so that a broken link can still be inspected
+    if !location.exists() {
+        return Err(AppError::FileNotFound(location.path().to_string_lossy().to_string()));
     }
 
-    FileStat::from_path(path)
+    FileStat::from_location(location)
 }
 
-pub fn create_directory(parent: &Path, name: &str) -> Result<DirResult> {
-    let new_path = parent.join(name);
+pub fn create_directory(parent: &Location, name: &str) -> Result<DirResult> {
+    let new_dir = parent.join(name);
 
-    if new_path.exists() {
-        return Err(AppError::FileExists(new_path.to_string_lossy().to_string()));
+    if new_dir.exists() {
+        return Err(AppError::FileExists(new_dir.path().to_string_lossy().to_string()));
     }
 
-    fs::create_dir(&new_path)?;
+    new_dir.create_dir()?;
 
     Ok(DirResult {
         success: true,
-        path: new_path.to_string_lossy().to_string(),
+        path: new_dir.path().to_string_lossy().to_string(),
         message: None,
     })
 }
 
-pub fn rename_item(src: &Path, new_name: &str) -> Result<RenameResult> {
+pub fn rename_item(src: &Location, new_name: &str) -> Result<RenameResult> {
+    // exists() doesn't follow links, so broken links are renamed and never replaced
     if !src.exists() {
-        return Err(AppError::FileNotFound(src.to_string_lossy().to_string()));
+        return Err(AppError::FileNotFound(src.path().to_string_lossy().to_string()));
     }
 
-    let parent = src.parent().ok_or_else(|| {
-        AppError::InvalidPath("Cannot get parent directory".to_string())
-    })?;
+    let dest = src.with_file_name(new_name)?;
 
-    let new_path = parent.join(new_name);
-
-    if new_path.exists() {
-        return Err(AppError::FileExists(new_path.to_string_lossy().to_string()));
+    if dest.exists() {
+        return Err(AppError::FileExists(dest.path().to_string_lossy().to_string()));
     }
 
-    fs::rename(src, &new_path)?;
+    src.rename_to(&dest)?;
 
     Ok(RenameResult {
         success: true,
-        old_path: src.to_string_lossy().to_string(),
-        new_path: new_path.to_string_lossy().to_string(),
+        old_path: src.path().to_string_lossy().to_string(),
+        new_path: dest.path().to_string_lossy().to_string(),
         message: None,
     })
 }
 
-pub fn move_items(src_paths: Vec<PathBuf>, dest_dir: &Path) -> Result<BatchResult> {
+/// Receives progress from long-running operations and tells them when to stop.
+pub trait Progress: Sync {
+    /// `bytes_total` is 0 when the size isn't known up front.
+    fn begin(&self, _items_total: usize, _bytes_total: u64) {}
+    fn item_started(&self, _path: &Path) {}
+    fn bytes_done(&self, _bytes: u64) {}
+    fn item_finished(&self) {}
+    fn is_cancelled(&self) -> bool {
+        false
+    }
+}
+
+/// Progress sink for callers that don't track progress.
+pub struct NoProgress;
+
+impl Progress for NoProgress {}
+
+/// What copy, move and trash do with a symlink.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SymlinkPolicy {
+    /// Act on the link itself
+    #[default]
+    Preserve,
+    /// Act on what the link points to
+    Follow,
+    /// Leave links alone
+    Skip,
+}
+
+impl SymlinkPolicy {
+    /// The entry a batch acts on for the selected `location`: the location
+    /// itself, or the target of a followed link. `None` if it is a skipped link.
+    ///
+    /// Move and trash only apply the policy to the selected items: a moved
+    /// directory keeps whatever links it contains. Copy applies it throughout
+    /// the tree.
+    pub fn resolve_item(self, location: &Location) -> Result<Option<Location>> {
+        let is_link = location
+            .symlink_metadata()
+            .map(|m| m.file_type().is_symlink())
+            .unwrap_or(false);
+
+        match self {
+            _ if !is_link => Ok(Some(location.clone())),
+            SymlinkPolicy::Preserve => Ok(Some(location.clone())),
+            SymlinkPolicy::Skip => Ok(None),
+            SymlinkPolicy::Follow => location.follow().map(Some),
+        }
+    }
+}
+
+pub fn move_items(
+    sandbox: &Sandbox,
+    src_paths: Vec<PathBuf>,
+    dest_dir: &Path,
+    policy: ConflictPolicy,
+    aside: &dyn SetAside,
+    links: SymlinkPolicy,
+    progress: &dyn Progress,
+) -> Result<BatchResult> {
     let batch_id = Uuid::new_v4().to_string();
     let mut results = Vec::new();
-    let mut processed = 0;
-    let mut failed = 0;
+    let dest_dir = sandbox.locate(dest_dir)?;
+    // A destination that doesn't exist yet is created inside its root
+    dest_dir.create_dir_all()?;
+
+    // Renames are instant, so bytes are only reported by the cross-device fallback
+    progress.begin(src_paths.len(), 0);
 
     for src_path in src_paths {
-        let file_name = src_path
-            .file_name()
-            .ok_or_else(|| AppError::InvalidPath("Invalid file name".to_string()))?;
-        
-        let dest_path = dest_dir.join(file_name);
-
-        let result = if dest_path.exists() {
-            failed += 1;
-            BatchItemResult {
-                path: src_path.to_string_lossy().to_string(),
-                success: false,
-                message: Some("Destination already exists".to_string()),
+        if progress.is_cancelled() {
+            results.push(BatchItemResult::cancelled(&src_path));
+            continue;
+        }
+        progress.item_started(&src_path);
+
+        // A followed link moves its target, under the target's own name
+        let src = match sandbox.locate(&src_path).and_then(|src| links.resolve_item(&src)) {
+            Ok(Some(src)) => src,
+            Ok(None) => {
+                results.push(BatchItemResult::skipped_with(&src_path, "Skipped: symbolic link"));
+                progress.item_finished();
+                continue;
+            }
+            Err(e) => {
+                results.push(BatchItemResult::failed(&src_path, e));
+                progress.item_finished();
+                continue;
+            }
+        };
+        let src_path = src.path();
+
+        let file_name = match src.file_name() {
+            Some(name) => name.to_owned(),
+            None => {
+                results.push(BatchItemResult::failed(&src_path, AppError::InvalidPath("Invalid file name".to_string())));
+                progress.item_finished();
+                continue;
             }
+        };
+
+        let dest = dest_dir.join(file_name);
+
+        // Checked before resolving, since Overwrite would set the source itself aside
+        let result = if is_same_path(&dest.path(), &src_path) {
+            BatchItemResult::failed(&src_path, AppError::InvalidPath("Source and destination are the same".to_string()))
+        } else if is_within(&dest_dir.path(), &src_path) {
+            BatchItemResult::failed(&src_path, AppError::InvalidPath("Cannot move a directory into itself".to_string()))
         } else {
-            match fs::rename(&src_path, &dest_path) {
-                Ok(_) => {
-                    processed += 1;
-                    BatchItemResult {
-                        path: src_path.to_string_lossy().to_string(),
-                        success: true,
-                        message: None,
-                    }
-                }
-                Err(_e) => {
-                    // Try copy and delete for cross-device moves
-                    match copy_and_delete(&src_path, &dest_path) {
-                        Ok(_) => {
-                            processed += 1;
-                            BatchItemResult {
-                                path: src_path.to_string_lossy().to_string(),
-                                success: true,
-                                message: None,
-                            }
-                        }
-                        Err(e) => {
-                            failed += 1;
-                            BatchItemResult {
-                                path: src_path.to_string_lossy().to_string(),
-                                success: false,
-                                message: Some(e.to_string()),
-                            }
-                        }
-                    }
-                }
+            match resolve_destination(dest, policy) {
+                Ok(Destination::Use(target, resolution)) => match move_entry(&src, &target, progress) {
+                    Ok(_) => BatchItemResult::done(&src_path, &target.path(), resolution),
+                    Err(AppError::Cancelled(_)) => BatchItemResult::cancelled(&src_path),
+                    Err(e) => BatchItemResult::failed(&src_path, e),
+                },
+                Ok(Destination::Replace(target)) => match replace(&target, aside, || move_entry(&src, &target, progress)) {
+                    Ok(replaced) => BatchItemResult::replaced(&src_path, &target.path(), &replaced),
+                    Err(AppError::Cancelled(_)) => BatchItemResult::cancelled(&src_path),
+                    Err(e) => BatchItemResult::failed(&src_path, e),
+                },
+                Ok(Destination::Skip) => BatchItemResult::skipped(&src_path),
+                Ok(Destination::Fail(error)) => BatchItemResult::failed(&src_path, error),
+                Err(e) => BatchItemResult::failed(&src_path, e),
             }
         };
 
         results.push(result);
+        progress.item_finished();
+    }
+
+    let mut batch = BatchResult::from_results(batch_id, results);
+    batch.cancelled = progress.is_cancelled();
+    Ok(batch)
+}
+
+/// Moves a single entry, falling back to copy and delete across devices.
+/// Symlinks inside the entry move as they are.
+pub fn move_entry(src: &Location, dest: &Location, progress: &dyn Progress) -> Result<()> {
+    match src.rename_to(dest) {
+        Ok(_) => Ok(()),
+        Err(AppError::NotAllowed(message)) => Err(AppError::NotAllowed(message)),
+        Err(_) => copy_and_delete(src, dest, progress),
     }
+}
+
+pub fn copy_items(
+    sandbox: &Sandbox,
+    src_paths: Vec<PathBuf>,
+    dest_dir: &Path,
+    policy: ConflictPolicy,
+    aside: &dyn SetAside,
+    links: SymlinkPolicy,
+    progress: &dyn Progress,
+) -> Result<BatchResult> {
+    let batch_id = Uuid::new_v4().to_string();
+    let mut results = Vec::new();
+    let dest_dir = sandbox.locate(dest_dir)?;
+    // A destination that doesn't exist yet is created inside its root
+    dest_dir.create_dir_all()?;
+
+    progress.begin(src_paths.len(), src_paths.iter().map(|p| tree_size(p)).sum());
+
+    for src_path in src_paths {
+        if progress.is_cancelled() {
+            results.push(BatchItemResult::cancelled(&src_path));
+            continue;
+        }
+        progress.item_started(&src_path);
+
+        let file_name = match src_path.file_name() {
+            Some(name) => name.to_owned(),
+            None => {
+                results.push(BatchItemResult::failed(&src_path, AppError::InvalidPath("Invalid file name".to_string())));
+                progress.item_finished();
+                continue;
+            }
+        };
+
+        let dest = dest_dir.join(file_name);
 
-    Ok(BatchResult {
-        success: failed == 0,
-        processed,
-        failed,
-        batch_id,
-        results,
+        // A followed link is copied under its own name
+        let source = match sandbox.locate(&src_path).and_then(|src| links.resolve_item(&src)) {
+            Ok(Some(source)) => source,
+            Ok(None) => {
+                results.push(BatchItemResult::skipped_with(&src_path, "Skipped: symbolic link"));
+                progress.item_finished();
+                continue;
+            }
+            Err(e) => {
+                results.push(BatchItemResult::failed(&src_path, e));
+                progress.item_finished();
+                continue;
+            }
+        };
+
+        let result = if !source.exists() {
+            BatchItemResult::failed(&src_path, AppError::FileNotFound(src_path.to_string_lossy().to_string()))
+        } else if is_same_path(&dest.path(), &source.path()) && policy == ConflictPolicy::Overwrite {
+            BatchItemResult::failed(&src_path, AppError::InvalidPath("Source and destination are the same".to_string()))
+        } else if is_within(&dest_dir.path(), &source.path()) {
+            BatchItemResult::failed(&src_path, AppError::InvalidPath("Cannot copy a directory into itself".to_string()))
+        } else {
+            match resolve_destination(dest, policy) {
+                Ok(Destination::Use(target, resolution)) => match copy_entry(&source, &target, links, progress) {
+                    Ok(_) => BatchItemResult::done(&src_path, &target.path(), resolution),
+                    Err(AppError::Cancelled(_)) => BatchItemResult::cancelled(&src_path),
+                    Err(e) => BatchItemResult::failed(&src_path, e),
+                },
+                Ok(Destination::Replace(target)) => match replace(&target, aside, || copy_entry(&source, &target, links, progress)) {
+                    Ok(replaced) => BatchItemResult::replaced(&src_path, &target.path(), &replaced),
+                    Err(AppError::Cancelled(_)) => BatchItemResult::cancelled(&src_path),
+                    Err(e) => BatchItemResult::failed(&src_path, e),
+                },
+                Ok(Destination::Skip) => BatchItemResult::skipped(&src_path),
+                Ok(Destination::Fail(error)) => BatchItemResult::failed(&src_path, error),
+                Err(e) => BatchItemResult::failed(&src_path, e),
+            }
+        };
+
+        results.push(result);
+        progress.item_finished();
+    }
+
+    let mut batch = BatchResult::from_results(batch_id, results);
+    batch.cancelled = progress.is_cancelled();
+    Ok(batch)
+}
+
+/// Copies a single entry, handling symlinks as `links` says. A failed or
+/// cancelled copy never leaves a half-written tree behind.
+pub fn copy_entry(src: &Location, dest: &Location, links: SymlinkPolicy, progress: &dyn Progress) -> Result<()> {
+    copy_tree(src, dest, links, &mut Vec::new(), progress).inspect_err(|_| {
+        let _ = dest.remove();
     })
 }
 
-fn copy_and_delete(src: &Path, dest: &Path) -> Result<()> {
-    if src.is_dir() {
-        copy_dir_recursive(src, dest)?;
-        fs::remove_dir_all(src)?;
+/// Copies `src` to `dest` and removes the source. A failed or cancelled
+/// copy is rolled back, so the source is only removed once `dest` is complete.
+pub fn copy_and_delete(src: &Location, dest: &Location, progress: &dyn Progress) -> Result<()> {
+    copy_entry(src, dest, SymlinkPolicy::Preserve, progress)?;
+    src.remove()
+}
+
+/// Copies a file, symlink or directory tree, preserving permissions and
+/// modification times. `visiting` holds the directories being copied, so a
+/// followed link can't lead back into one of them. A link that can't be
+/// followed, because it is broken, leads outside the roots or back into the
+/// tree, is copied as a link. Stops with `AppError::Cancelled` between
+/// entries once `progress` is cancelled.
+fn copy_tree(
+    src: &Location,
+    dest: &Location,
+    links: SymlinkPolicy,
+    visiting: &mut Vec<PathBuf>,
+    progress: &dyn Progress,
+) -> Result<()> {
+    if progress.is_cancelled() {
+        return Err(AppError::Cancelled(src.path().to_string_lossy().to_string()));
+    }
+
+    let metadata = src.symlink_metadata()?;
+    let file_type = metadata.file_type();
+
+    if file_type.is_symlink() {
+        match links {
+            SymlinkPolicy::Preserve => {}
+            SymlinkPolicy::Skip => return Ok(()),
+            SymlinkPolicy::Follow => {
+                if let Ok(target) = src.follow() {
+                    let target_path = target.path();
+                    let loops = visiting.iter().any(|dir| dir.starts_with(&target_path))
+                        || dest.path().parent().is_some_and(|parent| is_within(parent, &target_path));
+                    if !loops {
+                        return copy_tree(&target, dest, links, visiting, progress);
+                    }
+                }
+            }
+        }
+
+        let is_dir = src.metadata().is_ok_and(|m| m.is_dir());
+        dest.symlink(&src.read_link()?, is_dir)?;
+        dest.set_times_from(&metadata)?;
+        return Ok(());
+    }
+
+    if file_type.is_dir() {
+        dest.create_dir()?;
+
+        visiting.push(src.path().canonicalize()?);
+        for entry in src.read_dir()? {
+            let name = entry?.file_name();
+            copy_tree(&src.join(&name), &dest.join(&name), links, visiting, progress)?;
+        }
+        visiting.pop();
     } else {
-        fs::copy(src, dest)?;
-        fs::remove_file(src)?;
+        // Copying already carries over the permission bits
+        let bytes = src.copy_file_to(dest)?;
+        progress.bytes_done(bytes);
     }
+
+    // Directories get their metadata last, since writing children bumps the mtime
+    dest.set_permissions(metadata.permissions())?;
+    dest.set_times_from(&metadata)?;
+
     Ok(())
 }
 
-fn copy_dir_recursive(src: &Path, dest: &Path) -> Result<()> {
-    fs::create_dir_all(dest)?;
+/// Total size in bytes of the regular files under `path`, without following symlinks.
+pub fn tree_size(path: &Path) -> u64 {
+    let metadata = match fs::symlink_metadata(path) {
+        Ok(m) => m,
+        Err(_) => return 0,
+    };
+
+    if metadata.is_dir() {
+        fs::read_dir(path)
+            .map(|entries| {
+                entries
+                    .filter_map(|e| e.ok())
+                    .map(|e| tree_size(&e.path()))
+                    .sum()
+            })
+            .unwrap_or(0)
+    } else if metadata.is_file() {
+        metadata.len()
+    } else {
+        0
+    }
+}
+
+/// Whether both paths resolve to the same existing entry.
+fn is_same_path(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
 
-    for entry in fs::read_dir(src)? {
-        let entry = entry?;
-        let src_path = entry.path();
-        let dest_path = dest.join(entry.file_name());
+/// Whether `path` is `ancestor` itself or lies somewhere below it.
+fn is_within(path: &Path, ancestor: &Path) -> bool {
+    match (path.canonicalize(), ancestor.canonicalize()) {
+        (Ok(path), Ok(ancestor)) => path.starts_with(ancestor),
+        _ => false,
+    }
+}
 
-        if src_path.is_dir() {
-            copy_dir_recursive(&src_path, &dest_path)?;
-        } else {
-            fs::copy(&src_path, &dest_path)?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::sandbox::{AllowedRoot, RootMode};
+    use crate::fs::trash::TrashManager;
+    use filetime::FileTime;
+    use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
+
+    fn at(path: &Path) -> Location {
+        Sandbox::new(&[], false).locate(path).unwrap()
+    }
+
+    #[test]
+    fn test_list_directory_sorting_and_paging() {
+        let root = std::env::temp_dir().join(format!("fm-list-{}", Uuid::new_v4()));
+        fs::create_dir_all(root.join("zdir")).unwrap();
+        for (name, size) in [("file10.txt", 3), ("file2.txt", 1), ("File1.md", 2), (".hidden", 0)] {
+            fs::write(root.join(name), "x".repeat(size)).unwrap();
         }
+        let names = |page: DirPage| page.items.into_iter().map(|i| i.name).collect::<Vec<_>>();
+
+        let visible = ListOptions { show_hidden: false, ..Default::default() };
+        let page = list_directory(&at(&root), &visible).unwrap();
+        assert_eq!(page.total, 4);
+        assert_eq!(names(page), vec!["zdir", "File1.md", "file2.txt", "file10.txt"]);
+
+        let by_size = ListOptions { sort_by: SortKey::Size, direction: SortDirection::Desc, dirs_first: false, ..Default::default() };
+        assert_eq!(names(list_directory(&at(&root), &by_size).unwrap())[..3], ["zdir", "file10.txt", "File1.md"][..]);
+
+        // Hidden entries are listed unless asked otherwise
+        let paged = ListOptions { offset: 1, limit: Some(2), ..Default::default() };
+        let page = list_directory(&at(&root), &paged).unwrap();
+        assert_eq!((page.total, page.next_offset), (5, Some(3)));
+        assert_eq!(names(page), vec![".hidden", "File1.md"]);
+
+        fs::remove_dir_all(&root).unwrap();
     }
 
-    Ok(())
-}
\ No newline at end of file
+    #[test]
+    fn test_copy_items_preserves_tree() {
+        let root = std::env::temp_dir().join(format!("fm-copy-{}", Uuid::new_v4()));
+        let src = root.join("src");
+        let dest = root.join("dest");
+        fs::create_dir_all(src.join("nested")).unwrap();
+        fs::create_dir_all(&dest).unwrap();
+        fs::write(src.join("nested").join("a.txt"), "hello").unwrap();
+
+        let mtime = FileTime::from_unix_time(1_600_000_000, 0);
+        filetime::set_file_mtime(src.join("nested").join("a.txt"), mtime).unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink("nested/a.txt", src.join("link")).unwrap();
+
+        let sandbox = Sandbox::new(&[AllowedRoot { path: root.to_string_lossy().to_string(), mode: RootMode::ReadWrite }], false);
+        let trash = TrashManager::new(&root.join("app"));
+        let result = copy_items(&sandbox, vec![src.clone()], &dest, ConflictPolicy::Fail, &trash, SymlinkPolicy::Preserve, &NoProgress).unwrap();
+        assert!(result.success);
+
+        let copied = dest.join("src").join("nested").join("a.txt");
+        assert_eq!(fs::read_to_string(&copied).unwrap(), "hello");
+        let copied_mtime = FileTime::from_last_modification_time(&fs::metadata(&copied).unwrap());
+        assert_eq!(copied_mtime, mtime);
+        #[cfg(unix)]
+        assert_eq!(
+            fs::read_link(dest.join("src").join("link")).unwrap(),
+            PathBuf::from("nested/a.txt")
+        );
+
+        // A second copy conflicts, and copying into itself is refused
+        let conflict = copy_items(&sandbox, vec![src.clone()], &dest, ConflictPolicy::Fail, &trash, SymlinkPolicy::Preserve, &NoProgress).unwrap();
+        assert!(!conflict.success);
+        assert_eq!(conflict.results[0].error_code.as_deref(), Some("FILE_EXISTS"));
+        let into_itself = copy_items(&sandbox, vec![src.clone()], &src.join("nested"), ConflictPolicy::Fail, &trash, SymlinkPolicy::Preserve, &NoProgress).unwrap();
+        assert_eq!(into_itself.results[0].error_code.as_deref(), Some("INVALID_PATH"));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// Cancels the batch as soon as its first item starts.
+    struct CancelOnStart(AtomicBool);
+
+    impl Progress for CancelOnStart {
+        fn item_started(&self, _path: &Path) {
+            self.0.store(true, AtomicOrdering::Relaxed);
+        }
+        fn is_cancelled(&self) -> bool {
+            self.0.load(AtomicOrdering::Relaxed)
+        }
+    }
+
+    #[test]
+    fn test_overwrite_sets_replaced_entry_aside() {
+        let root = std::env::temp_dir().join(format!("fm-overwrite-{}", Uuid::new_v4()));
+        let (src, dest) = (root.join("src"), root.join("dest"));
+        fs::create_dir_all(&src).unwrap();
+        fs::create_dir_all(&dest).unwrap();
+        fs::write(src.join("a.txt"), "new").unwrap();
+        fs::write(dest.join("a.txt"), "old").unwrap();
+
+        let sandbox = Sandbox::new(&[AllowedRoot { path: root.to_string_lossy().to_string(), mode: RootMode::ReadWrite }], false);
+        let trash = TrashManager::new(&root.join("app"));
+
+        // A write that fails puts the replaced entry back
+        let cancelled = copy_items(&sandbox, vec![src.join("a.txt")], &dest, ConflictPolicy::Overwrite, &trash, SymlinkPolicy::Preserve, &CancelOnStart(AtomicBool::new(false))).unwrap();
+        assert!(cancelled.cancelled && cancelled.results[0].replaced_path.is_none());
+        assert_eq!(fs::read_to_string(dest.join("a.txt")).unwrap(), "old");
+
+        let result = move_items(&sandbox, vec![src.join("a.txt")], &dest, ConflictPolicy::Overwrite, &trash, SymlinkPolicy::Preserve, &NoProgress).unwrap();
+        let item = &result.results[0];
+        assert_eq!(item.resolution, ConflictResolution::Overwritten);
+        assert_eq!(fs::read_to_string(dest.join("a.txt")).unwrap(), "new");
+        let replaced = PathBuf::from(item.replaced_path.clone().unwrap());
+        assert_eq!(fs::read_to_string(&replaced).unwrap(), "old");
+
+        // The replaced entry is an ordinary trash item that comes back on undo
+        assert_eq!(trash.list_items().unwrap()[0].original_path, dest.join("a.txt").to_string_lossy());
+        fs::rename(dest.join("a.txt"), src.join("a.txt")).unwrap();
+        trash.restore_item(&replaced, &sandbox.locate(&dest.join("a.txt")).unwrap()).unwrap();
+        assert_eq!(fs::read_to_string(dest.join("a.txt")).unwrap(), "old");
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_get_file_stat_details() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let root = std::env::temp_dir().join(format!("fm-stat-{}", Uuid::new_v4()));
+        fs::create_dir_all(&root).unwrap();
+        let file = root.join("notes.txt");
+        fs::write(&file, "plain text").unwrap();
+        fs::set_permissions(&file, fs::Permissions::from_mode(0o640)).unwrap();
+        fs::write(root.join("image.png"), b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR").unwrap();
+        std::os::unix::fs::symlink("notes.txt", root.join("link")).unwrap();
+        std::os::unix::fs::symlink("missing", root.join("broken")).unwrap();
+
+        let stat = get_file_stat(&at(&file)).unwrap();
+        assert_eq!(stat.permissions.as_deref(), Some("-rw-r-----"));
+        assert_eq!(stat.mode.as_deref(), Some("0640"));
+        assert_eq!(stat.mime_type.as_deref(), Some("text/plain"));
+        assert_eq!(stat.nlink, Some(1));
+        assert!(!stat.is_symlink);
+
+        let png = get_file_stat(&at(&root.join("image.png"))).unwrap();
+        assert_eq!(png.mime_type.as_deref(), Some("image/png"));
+
+        let link = get_file_stat(&at(&root.join("link"))).unwrap();
+        assert!(link.is_symlink);
+        assert_eq!(link.symlink_target.as_deref(), Some("notes.txt"));
+        assert_eq!((link.inode, link.size), (stat.inode, stat.size));
+        assert!(FileItem::from_location(&at(&root.join("link"))).unwrap().is_symlink);
+
+        let broken = get_file_stat(&at(&root.join("broken"))).unwrap();
+        assert_eq!(broken.permissions.as_deref().map(|p| &p[..1]), Some("l"));
+        assert_eq!(broken.mime_type.as_deref(), Some("inode/symlink"));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlink_policies() {
+        use std::os::unix::fs::symlink;
+
+        let root = std::env::temp_dir().join(format!("fm-links-{}", Uuid::new_v4()));
+        let src = root.join("src");
+        let outside = std::env::temp_dir().join(format!("fm-outside-{}", Uuid::new_v4()));
+        fs::create_dir_all(src.join("data")).unwrap();
+        fs::create_dir_all(&outside).unwrap();
+        fs::write(src.join("data").join("a.txt"), "hello").unwrap();
+        symlink("data/a.txt", src.join("file-link")).unwrap();
+        symlink("..", src.join("data").join("up")).unwrap();
+        symlink(&outside, src.join("escape")).unwrap();
+        symlink(src.join("data"), src.join("absolute")).unwrap();
+        symlink("missing", src.join("broken")).unwrap();
+
+        // Broken links are listed rather than dropped
+        let page = list_directory(&at(&src), &ListOptions::default()).unwrap();
+        let broken = page.items.iter().find(|i| i.name == "broken").unwrap();
+        assert!(broken.is_symlink && !broken.is_dir);
+
+        let sandbox = Sandbox::new(&[AllowedRoot { path: root.to_string_lossy().to_string(), mode: RootMode::ReadWrite }], false);
+        let trash = TrashManager::new(&root.join("app"));
+        let copy = |policy, dest: &str| {
+            let dest = root.join(dest);
+            fs::create_dir_all(&dest).unwrap();
+            copy_items(&sandbox, vec![src.clone()], &dest, ConflictPolicy::Fail, &trash, policy, &NoProgress).unwrap();
+            dest.join("src")
+        };
+        let is_link = |path: PathBuf| fs::symlink_metadata(path).unwrap().file_type().is_symlink();
+
+        // Following copies content, but never loops or leaves the roots
+        let followed = copy(SymlinkPolicy::Follow, "followed");
+        assert_eq!(fs::read_to_string(followed.join("file-link")).unwrap(), "hello");
+        assert!(!is_link(followed.join("file-link")));
+        assert!(is_link(followed.join("data").join("up")));
+        assert!(is_link(followed.join("escape")));
+        // Absolute targets are located again rather than resolved by the handle
+        assert_eq!(fs::read_to_string(followed.join("absolute").join("a.txt")).unwrap(), "hello");
+        assert!(is_link(followed.join("broken")));
+
+        let skipped = copy(SymlinkPolicy::Skip, "skipped");
+        assert!(skipped.join("data").join("a.txt").exists());
+        assert!(fs::symlink_metadata(skipped.join("file-link")).is_err());
+
+        // Following a selected link that leaves the roots fails the item
+        let result = move_items(&sandbox, vec![src.join("escape")], &root, ConflictPolicy::Fail, &trash, SymlinkPolicy::Follow, &NoProgress).unwrap();
+        assert_eq!(result.failed, 1);
+        assert!(outside.exists());
+
+        fs::remove_dir_all(&root).unwrap();
+        fs::remove_dir_all(&outside).unwrap();
+    }
+}
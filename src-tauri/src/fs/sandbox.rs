@@ -0,0 +1,693 @@
+//! Root confinement through directory handles.
+//!
+//! Every allowed root is opened once as a `cap_std::fs::Dir`, and entries
+//! inside it are reached with paths relative to that handle. cap-std resolves
+//! each component itself (openat-style) and refuses any `..`, absolute path
+//! or symlink that would lead out of the handle, so there is no window
+//! between checking a path and acting on it in which a swapped-in symlink
+//! could redirect the operation outside the root.
+//!
+//! Paths are only resolved up front to pick the handle to go through.
+//! A symlink the handle can't resolve by itself, because its target is
+//! absolute or lies in another root, is followed by locating the target
+//! again, so the operation still ends up confined to a root.
+//!
+//! Each root also has a `RootMode`, checked by every operation that writes
+//! or removes something through a `Location`.
+
+use crate::error::{AppError, Result};
+use cap_fs_ext::{DirExt, SystemTimeSpec};
+use cap_std::ambient_authority;
+use cap_std::fs::{Dir, File, FileType, Metadata, OpenOptions, Permissions, ReadDir};
+#[cfg(unix)]
+use cap_std::fs::OpenOptionsExt;
+use serde::{Deserialize, Serialize};
+use std::ffi::OsStr;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+/// What may be done inside an allowed root. Reading is always allowed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RootMode {
+    ReadOnly,
+    #[default]
+    ReadWrite,
+    /// Anything but removing entries or moving them out of the root
+    NoDelete,
+}
+
+impl RootMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RootMode::ReadOnly => "read_only",
+            RootMode::ReadWrite => "read_write",
+            RootMode::NoDelete => "no_delete",
+        }
+    }
+
+    /// A value this version doesn't know is read as `ReadOnly`, so a stored
+    /// mode is never widened.
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "read_write" => RootMode::ReadWrite,
+            "no_delete" => RootMode::NoDelete,
+            _ => RootMode::ReadOnly,
+        }
+    }
+
+    fn allows(&self, access: Access) -> bool {
+        match self {
+            RootMode::ReadOnly => false,
+            RootMode::ReadWrite => true,
+            RootMode::NoDelete => access == Access::Write,
+        }
+    }
+}
+
+/// Access beyond reading that an operation needs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    /// Creating or changing entries
+    Write,
+    /// Removing entries, or moving them out of their root
+    Delete,
+}
+
+/// A directory the app may access, and how.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AllowedRoot {
+    pub path: String,
+    #[serde(default)]
+    pub mode: RootMode,
+}
+
+/// The allowed roots, and what an empty list means.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RootSettings {
+    pub roots: Vec<AllowedRoot>,
+    /// With no roots, deny every path instead of allowing every path
+    pub strict: bool,
+}
+
+/// The allowed roots, opened as directory handles.
+#[derive(Clone)]
+pub struct Sandbox {
+    /// `None` when no roots are configured and every path is allowed
+    roots: Option<Arc<Vec<Root>>>,
+}
+
+struct Root {
+    /// Canonical path of `dir`
+    path: PathBuf,
+    dir: Arc<Dir>,
+    mode: RootMode,
+}
+
+impl Sandbox {
+    /// Opens a handle on each root. Roots that can't be opened are left out,
+    /// so they allow nothing. Without roots everything is allowed, unless
+    /// `strict` is set.
+    pub fn new(roots: &[AllowedRoot], strict: bool) -> Self {
+        if roots.is_empty() && !strict {
+            return Sandbox { roots: None };
+        }
+
+        let mut opened: Vec<Root> = roots
+            .iter()
+            .filter_map(|root| {
+                let path = Path::new(&root.path).canonicalize().ok()?;
+                let dir = Dir::open_ambient_dir(&path, ambient_authority()).ok()?;
+                Some(Root { path, dir: Arc::new(dir), mode: root.mode })
+            })
+            .collect();
+        // Most specific root first, so nested roots confine as tightly as possible
+        opened.sort_by_key(|root| std::cmp::Reverse(root.path.as_os_str().len()));
+
+        Sandbox { roots: Some(Arc::new(opened)) }
+    }
+
+    /// Resolves `path` to a location inside one of the roots. The path
+    /// doesn't have to exist yet, so destinations can be located before
+    /// they are created.
+    pub fn locate(&self, path: &Path) -> Result<Location> {
+        let roots = match &self.roots {
+            Some(roots) => roots,
+            None => return Location::ambient(path),
+        };
+
+        if !path.is_absolute() {
+            return Err(AppError::InvalidPath(format!("{} is not an absolute path", path.display())));
+        }
+        let path = normalize(path);
+
+        let find = |path: &Path| {
+            roots.iter().find_map(|root| {
+                let rel = path.strip_prefix(&root.path).ok()?;
+                Some(Location {
+                    dir: root.dir.clone(),
+                    base: root.path.clone(),
+                    rel: rel.to_path_buf(),
+                    mode: root.mode,
+                    sandbox: self.clone(),
+                })
+            })
+        };
+
+        // The parent may reach a root through symlinks, such as /tmp on
+        // macOS; the entry itself is left alone so links can be acted on
+        let resolved = match (path.parent(), path.file_name()) {
+            (Some(parent), Some(name)) => canonicalize_existing(parent).map(|parent| parent.join(name)),
+            _ => None,
+        };
+        resolved
+            .and_then(|resolved| find(&resolved))
+            .or_else(|| find(&path))
+            .ok_or_else(|| AppError::NotAllowed(format!("{} is outside the allowed roots", path.display())))
+    }
+
+    /// Whether `path` lies inside one of the roots.
+    pub fn contains(&self, path: &Path) -> bool {
+        self.roots.is_none() || self.locate(path).is_ok()
+    }
+}
+
+/// An entry addressed relative to a directory handle. Whether it exists or
+/// not, nothing done through a `Location` leaves the handle's directory.
+#[derive(Clone)]
+pub struct Location {
+    dir: Arc<Dir>,
+    /// Path of `dir`
+    base: PathBuf,
+    /// Path of the entry relative to `dir`; empty for `dir` itself
+    rel: PathBuf,
+    mode: RootMode,
+    /// The roots `dir` was taken from, for following links out of it
+    sandbox: Sandbox,
+}
+
+impl Location {
+    /// A location reached through its closest existing ancestor, opened
+    /// without confinement. For paths the app itself owns, such as its
+    /// trash, and for every path when no roots are configured.
+    pub fn ambient(path: &Path) -> Result<Self> {
+        let base = match path.parent() {
+            None => path,
+            Some(parent) if parent.as_os_str().is_empty() => Path::new("."),
+            Some(parent) => parent.ancestors().find(|a| a.is_dir()).unwrap_or(parent),
+        };
+        let rel = match path.strip_prefix(base) {
+            Ok(rel) => rel.to_path_buf(),
+            Err(_) => PathBuf::from(path.file_name().unwrap_or_default()),
+        };
+
+        let dir = Dir::open_ambient_dir(base, ambient_authority())?;
+        Ok(Location {
+            dir: Arc::new(dir),
+            base: base.to_path_buf(),
+            rel,
+            mode: RootMode::ReadWrite,
+            sandbox: Sandbox { roots: None },
+        })
+    }
+
+    /// Full path, for reporting and logging.
+    pub fn path(&self) -> PathBuf {
+        if self.rel.as_os_str().is_empty() {
+            self.base.clone()
+        } else {
+            self.base.join(&self.rel)
+        }
+    }
+
+    pub fn file_name(&self) -> Option<&OsStr> {
+        self.rel.file_name().or_else(|| self.base.file_name())
+    }
+
+    /// The entry `name` inside this directory.
+    pub fn join(&self, name: impl AsRef<Path>) -> Location {
+        Location { rel: self.rel.join(name), ..self.clone() }
+    }
+
+    /// The entry called `name` next to this one.
+    pub fn with_file_name(&self, name: impl AsRef<OsStr>) -> Result<Location> {
+        if self.rel.as_os_str().is_empty() {
+            return Err(AppError::NotAllowed(format!("{} has no siblings here", self.path().display())));
+        }
+        Ok(Location { rel: self.rel.with_file_name(name), ..self.clone() })
+    }
+
+    /// The directory containing this entry, if it is inside the same handle.
+    pub fn parent(&self) -> Option<Location> {
+        let parent = self.rel.parent()?;
+        Some(Location { rel: parent.to_path_buf(), ..self.clone() })
+    }
+
+    /// Where a symlink at this location leads, or the location itself with
+    /// its symlinked components resolved. Fails rather than leave the roots.
+    pub fn follow(&self) -> Result<Location> {
+        let path = self.path();
+        let target = path
+            .canonicalize()
+            .map_err(|_| AppError::FileNotFound(format!("{} is a broken link", path.display())))?;
+        self.sandbox.locate(&target)
+    }
+
+    pub fn exists(&self) -> bool {
+        self.symlink_metadata().is_ok()
+    }
+
+    pub fn symlink_metadata(&self) -> Result<Metadata> {
+        self.dir.symlink_metadata(self.target()).map_err(|e| self.error(e))
+    }
+
+    /// Metadata of what the entry resolves to, inside the roots.
+    pub fn metadata(&self) -> Result<Metadata> {
+        self.following(|dir, path| dir.metadata(path))
+    }
+
+    pub fn read_dir(&self) -> Result<ReadDir> {
+        self.following(|dir, path| dir.read_dir(path))
+    }
+
+    pub fn open(&self) -> Result<File> {
+        self.following(|dir, path| dir.open(path))
+    }
+
+    /// Opens the entry for reading without waiting on a FIFO or device
+    /// that took the place of a file, so it can be checked and passed over.
+    pub fn open_nonblocking(&self) -> Result<File> {
+        let mut options = OpenOptions::new();
+        options.read(true);
+        #[cfg(unix)]
+        options.custom_flags(libc::O_NONBLOCK);
+        self.following(|dir, path| dir.open_with(path, &options))
+    }
+
+    /// Every entry below this directory, down to `max_depth` levels (1 for
+    /// the direct children only). Symlinks are listed but never descended
+    /// into, and directories that can't be read are passed over.
+    pub fn walk(&self, max_depth: Option<usize>) -> Walk {
+        let mut walk = Walk { pending: Vec::new(), max_depth };
+        walk.descend(self, 1);
+        walk
+    }
+
+    /// The stored target of a symlink, which may point anywhere.
+    pub fn read_link(&self) -> Result<PathBuf> {
+        self.dir.read_link_contents(self.target()).map_err(|e| self.error(e))
+    }
+
+    /// Fails unless the root this entry is in allows `access`. Removing an
+    /// entry also fails when a root that forbids it lies inside the entry.
+    pub fn require(&self, access: Access) -> Result<()> {
+        if self.mode.allows(access) {
+            return match access {
+                Access::Delete => self.require_movable(),
+                Access::Write => Ok(()),
+            };
+        }
+
+        let reason = match self.mode {
+            RootMode::NoDelete => "doesn't allow removing entries",
+            _ => "is read-only",
+        };
+        Err(AppError::NotAllowed(format!("{}: the root it is in {}", self.path().display(), reason)))
+    }
+
+    /// Fails when a root that doesn't allow removing entries is this entry
+    /// or lies inside it, since moving the entry would take that root along.
+    fn require_movable(&self) -> Result<()> {
+        let path = self.path();
+        let nested = self
+            .sandbox
+            .roots
+            .iter()
+            .flat_map(|roots| roots.iter())
+            .find(|root| root.path.starts_with(&path) && !root.mode.allows(Access::Delete));
+
+        match nested {
+            Some(root) => Err(AppError::NotAllowed(format!(
+                "{}: it contains the root {}, which doesn't allow removing entries",
+                path.display(),
+                root.path.display()
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// Opens the file for writing, creating or truncating it.
+    pub fn create(&self) -> Result<File> {
+        self.require(Access::Write)?;
+        self.dir.create(self.target()).map_err(|e| self.error(e))
+    }
+
+    pub fn create_dir(&self) -> Result<()> {
+        self.require(Access::Write)?;
+        self.dir.create_dir(self.target()).map_err(|e| self.error(e))
+    }
+
+    /// Creates the directory and its missing parents. Succeeds without
+    /// writing anything if it already exists.
+    pub fn create_dir_all(&self) -> Result<()> {
+        if self.metadata().is_ok_and(|m| m.is_dir()) {
+            return Ok(());
+        }
+        self.require(Access::Write)?;
+        self.dir.create_dir_all(self.target()).map_err(|e| self.error(e))
+    }
+
+    pub fn remove_dir(&self) -> Result<()> {
+        self.require(Access::Delete)?;
+        self.dir.remove_dir(self.target()).map_err(|e| self.error(e))
+    }
+
+    /// Removes a file, symlink or directory tree without following symlinks.
+    pub fn remove(&self) -> Result<()> {
+        self.require(Access::Delete)?;
+        if self.symlink_metadata()?.is_dir() {
+            self.dir.remove_dir_all(self.target()).map_err(|e| self.error(e))
+        } else {
+            self.dir.remove_file_or_symlink(self.target()).map_err(|e| self.error(e))
+        }
+    }
+
+    /// Renames this entry to `dest`, which may be under another handle on
+    /// the same filesystem. Moving it out of its root counts as removing it,
+    /// and an entry containing a root that forbids removing can't be moved.
+    pub fn rename_to(&self, dest: &Location) -> Result<()> {
+        self.require(Access::Write)?;
+        dest.require(Access::Write)?;
+        if !Arc::ptr_eq(&self.dir, &dest.dir) {
+            self.require(Access::Delete)?;
+        }
+        self.require_movable()?;
+
+        self.dir
+            .rename(self.target(), &dest.dir, dest.target())
+            .map_err(|e| self.error(e))
+    }
+
+    /// Copies the contents and permissions of a regular file to `dest`.
+    pub fn copy_file_to(&self, dest: &Location) -> Result<u64> {
+        dest.require(Access::Write)?;
+        self.dir
+            .copy(self.target(), &dest.dir, dest.target())
+            .map_err(|e| self.error(e))
+    }
+
+    /// Creates a symlink here pointing at `target`, stored as given.
+    #[cfg(unix)]
+    pub fn symlink(&self, target: &Path, _is_dir: bool) -> Result<()> {
+        self.require(Access::Write)?;
+        self.dir.symlink_contents(target, self.target()).map_err(|e| self.error(e))
+    }
+
+    #[cfg(windows)]
+    pub fn symlink(&self, target: &Path, is_dir: bool) -> Result<()> {
+        self.require(Access::Write)?;
+        let created = if is_dir {
+            self.dir.symlink_dir(target, self.target())
+        } else {
+            self.dir.symlink_file(target, self.target())
+        };
+        created.map_err(|e| self.error(e))
+    }
+
+    pub fn set_permissions(&self, permissions: Permissions) -> Result<()> {
+        self.require(Access::Write)?;
+        self.dir
+            .set_permissions(self.target(), permissions)
+            .map_err(|e| self.error(e))
+    }
+
+    /// Gives the entry the access and modification times in `metadata`.
+    pub fn set_times_from(&self, metadata: &Metadata) -> Result<()> {
+        let atime = metadata.accessed().ok().map(SystemTimeSpec::from);
+        let mtime = metadata.modified().ok().map(SystemTimeSpec::from);
+        self.require(Access::Write)?;
+
+        let set = if metadata.file_type().is_symlink() {
+            self.dir.set_symlink_times(self.target(), atime, mtime)
+        } else {
+            self.dir.set_times(self.target(), atime, mtime)
+        };
+        set.map_err(|e| self.error(e))
+    }
+
+    /// Runs an operation that follows symlinks. When the handle refuses a
+    /// link, the operation is retried on the link's target if that is
+    /// inside one of the roots, or anywhere when no roots are configured.
+    fn following<T>(&self, op: impl Fn(&Dir, &Path) -> io::Result<T>) -> Result<T> {
+        match op(&self.dir, self.target()) {
+            Err(e) if self.escapes(&e) => {
+                let target = self.follow()?;
+                op(&target.dir, target.target()).map_err(|e| target.error(e))
+            }
+            result => result.map_err(|e| self.error(e)),
+        }
+    }
+
+    fn target(&self) -> &Path {
+        if self.rel.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            &self.rel
+        }
+    }
+
+    /// cap-std reports an attempt to leave the handle as PermissionDenied;
+    /// it surfaces as `NotAllowed` so it can't be mistaken for file permissions.
+    fn error(&self, error: io::Error) -> AppError {
+        if self.sandbox.roots.is_some() && self.escapes(&error) {
+            return AppError::NotAllowed(format!("{} leads outside the allowed roots", self.path().display()));
+        }
+        error.into()
+    }
+
+    /// Whether a refusal came from the handle rather than from file
+    /// permissions, told apart by resolving the entry without the handle:
+    /// only a path that leads out of the handle's directory is refused for
+    /// escaping it. Ambient handles confine too, to the entry's parent.
+    fn escapes(&self, error: &io::Error) -> bool {
+        error.kind() == io::ErrorKind::PermissionDenied
+            && resolve_links(&self.path()).is_some_and(|resolved| !resolved.starts_with(&self.base))
+    }
+}
+
+/// Entries found by `Location::walk`, depth first.
+pub struct Walk {
+    pending: Vec<(Location, FileType, usize)>,
+    max_depth: Option<usize>,
+}
+
+impl Walk {
+    fn descend(&mut self, dir: &Location, depth: usize) {
+        if self.max_depth.is_some_and(|max| depth > max) {
+            return;
+        }
+        let entries = match dir.read_dir() {
+            Ok(entries) => entries,
+            Err(_) => return,
+        };
+        for entry in entries.filter_map(|e| e.ok()) {
+            if let Ok(file_type) = entry.file_type() {
+                self.pending.push((dir.join(entry.file_name()), file_type, depth));
+            }
+        }
+    }
+}
+
+impl Iterator for Walk {
+    /// The entry and its own type, with symlinks not followed
+    type Item = (Location, FileType);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (location, file_type, depth) = self.pending.pop()?;
+        if file_type.is_dir() {
+            self.descend(&location, depth + 1);
+        }
+        Some((location, file_type))
+    }
+}
+
+/// Maximum symlinks followed while resolving one path, as in most kernels.
+const MAX_LINK_HOPS: usize = 40;
+
+/// Where `path` leads with every symlink along it followed, including
+/// dangling ones. `None` for link loops.
+fn resolve_links(path: &Path) -> Option<PathBuf> {
+    let mut resolved = PathBuf::new();
+    let mut pending: Vec<PathBuf> = path.components().rev().map(|c| PathBuf::from(c.as_os_str())).collect();
+    let mut hops = 0;
+
+    while let Some(component) = pending.pop() {
+        match component.components().next() {
+            Some(Component::CurDir) | None => {}
+            Some(Component::ParentDir) => {
+                resolved.pop();
+            }
+            Some(Component::Normal(name)) => {
+                let next = resolved.join(name);
+                match std::fs::symlink_metadata(&next) {
+                    Ok(metadata) if metadata.file_type().is_symlink() => {
+                        hops += 1;
+                        if hops > MAX_LINK_HOPS {
+                            return None;
+                        }
+                        let target = std::fs::read_link(&next).ok()?;
+                        pending.extend(target.components().rev().map(|c| PathBuf::from(c.as_os_str())));
+                    }
+                    _ => resolved = next,
+                }
+            }
+            // An absolute target starts over from its root
+            Some(root) => resolved.push(root),
+        }
+    }
+    Some(resolved)
+}
+
+/// Resolves `.` and `..` without touching the filesystem.
+fn normalize(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                out.pop();
+            }
+            other => out.push(other),
+        }
+    }
+    out
+}
+
+/// `path` with its longest existing ancestor canonicalized.
+pub(crate) fn canonicalize_existing(path: &Path) -> Option<PathBuf> {
+    let existing = path.ancestors().find(|a| a.exists())?;
+    let rest = path.strip_prefix(existing).ok()?;
+    Some(existing.canonicalize().ok()?.join(rest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_locations_stay_inside_roots() {
+        let base = std::env::temp_dir().join(format!("fm-sandbox-{}", uuid::Uuid::new_v4()));
+        let root = base.join("root");
+        let outside = base.join("outside");
+        fs::create_dir_all(root.join("docs")).unwrap();
+        fs::create_dir_all(&outside).unwrap();
+        fs::write(outside.join("secret.txt"), "secret").unwrap();
+
+        let sandbox = Sandbox::new(&[AllowedRoot { path: root.to_string_lossy().to_string(), mode: RootMode::ReadWrite }], false);
+        assert!(sandbox.contains(&root.join("docs")));
+        // Destinations that don't exist yet are located too
+        assert!(sandbox.contains(&root.join("docs/new/deeper")));
+        assert!(!sandbox.contains(&root.join("../outside")));
+        assert!(!sandbox.contains(&outside));
+        assert!(Sandbox::new(&[], false).contains(&outside));
+        assert!(!Sandbox::new(&[], true).contains(&outside));
+
+        // A symlink swapped in after the path was located can't redirect the operation
+        let target = sandbox.locate(&root.join("docs/secret.txt")).unwrap();
+        fs::remove_dir(root.join("docs")).unwrap();
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(&outside, root.join("docs")).unwrap();
+            assert!(matches!(target.metadata(), Err(AppError::NotAllowed(_))));
+            assert!(matches!(target.remove(), Err(AppError::NotAllowed(_))));
+            assert!(outside.join("secret.txt").exists());
+
+            // Links out of the roots are followed there and refused, not
+            // mistaken for missing permissions
+            std::os::unix::fs::symlink(&outside, root.join("out")).unwrap();
+            assert!(matches!(sandbox.locate(&root.join("out/secret.txt")).unwrap().metadata(), Err(AppError::NotAllowed(_))));
+            std::os::unix::fs::symlink(outside.join("gone"), root.join("dangling")).unwrap();
+            assert!(matches!(sandbox.locate(&root.join("dangling/x")).unwrap().metadata(), Err(AppError::FileNotFound(_))));
+        }
+
+        fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_links_followed_without_roots() {
+        let base = std::env::temp_dir().join(format!("fm-no-roots-{}", uuid::Uuid::new_v4()));
+        let (home, data) = (base.join("home"), base.join("data/docs"));
+        fs::create_dir_all(&home).unwrap();
+        fs::create_dir_all(&data).unwrap();
+        fs::write(data.join("a.txt"), "a").unwrap();
+        std::os::unix::fs::symlink(&data, home.join("Documents")).unwrap();
+        std::os::unix::fs::symlink("../data/docs/a.txt", home.join("a.txt")).unwrap();
+
+        // Links leaving the parent are followed wherever they lead
+        let sandbox = Sandbox::new(&[], false);
+        let documents = sandbox.locate(&home.join("Documents")).unwrap();
+        assert!(documents.metadata().unwrap().is_dir());
+        assert_eq!(documents.read_dir().unwrap().count(), 1);
+        assert!(sandbox.locate(&home.join("a.txt")).unwrap().open().is_ok());
+
+        fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn test_root_modes() {
+        let base = std::env::temp_dir().join(format!("fm-modes-{}", uuid::Uuid::new_v4()));
+        let (read_only, no_delete, read_write) = (base.join("ro"), base.join("nd"), base.join("rw"));
+        for dir in [&read_only, &no_delete, &read_write] {
+            fs::create_dir_all(dir).unwrap();
+            fs::write(dir.join("a.txt"), "a").unwrap();
+        }
+
+        let sandbox = Sandbox::new(
+            &[
+                AllowedRoot { path: read_only.to_string_lossy().to_string(), mode: RootMode::ReadOnly },
+                AllowedRoot { path: no_delete.to_string_lossy().to_string(), mode: RootMode::NoDelete },
+                AllowedRoot { path: read_write.to_string_lossy().to_string(), mode: RootMode::ReadWrite },
+            ],
+            false,
+        );
+        let at = |path: PathBuf| sandbox.locate(&path).unwrap();
+        let denied = |result: Result<()>| matches!(result, Err(AppError::NotAllowed(_)));
+
+        assert!(at(read_only.join("a.txt")).metadata().is_ok());
+        assert!(denied(at(read_only.join("new")).create_dir()));
+        assert!(denied(at(read_only.join("a.txt")).remove()));
+
+        // Inside a no-delete root entries can be added and renamed, but not removed
+        assert!(at(no_delete.join("new")).create_dir().is_ok());
+        assert!(at(no_delete.join("a.txt")).rename_to(&at(no_delete.join("b.txt"))).is_ok());
+        assert!(denied(at(no_delete.join("b.txt")).remove()));
+        assert!(denied(at(no_delete.join("b.txt")).rename_to(&at(read_write.join("b.txt")))));
+        assert!(at(read_write.join("a.txt")).rename_to(&at(no_delete.join("a.txt"))).is_ok());
+
+        // A folder holding a more restrictive root can't be removed or moved away
+        let nested = read_write.join("projects/locked");
+        fs::create_dir_all(&nested).unwrap();
+        fs::write(read_write.join("projects/c.txt"), "c").unwrap();
+        let sandbox = Sandbox::new(
+            &[
+                AllowedRoot { path: read_write.to_string_lossy().to_string(), mode: RootMode::ReadWrite },
+                AllowedRoot { path: nested.to_string_lossy().to_string(), mode: RootMode::NoDelete },
+            ],
+            false,
+        );
+        let at = |path: PathBuf| sandbox.locate(&path).unwrap();
+        assert!(denied(at(read_write.join("projects")).remove()));
+        assert!(denied(at(read_write.join("projects")).rename_to(&at(read_write.join("moved")))));
+        assert!(denied(at(read_write.join("projects")).join("locked").remove()));
+        assert!(at(read_write.join("projects/c.txt")).remove().is_ok());
+        assert!(nested.exists());
+
+        assert_eq!(RootMode::parse("no_delete"), RootMode::NoDelete);
+        assert_eq!(RootMode::parse("append_only"), RootMode::ReadOnly);
+
+        fs::remove_dir_all(&base).unwrap();
+    }
+}
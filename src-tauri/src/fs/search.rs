@@ -0,0 +1,239 @@
+use crate::error::{AppError, Result};
+use crate::fs::operations::Progress;
+use crate::fs::sandbox::Location;
+use crate::fs::FileItem;
+use chrono::{DateTime, Utc};
+use globset::{GlobBuilder, GlobMatcher};
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+/// Matches sent per chunk at most.
+const CHUNK_SIZE: usize = 200;
+
+/// Longest a match waits before its chunk is sent anyway.
+const CHUNK_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How the query is matched against file names.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NameMatch {
+    #[default]
+    Substring,
+    Glob,
+    Regex,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EntryKind {
+    File,
+    Dir,
+}
+
+/// Optional narrowing of a search. Everything left out matches.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SearchFilters {
+    pub match_mode: NameMatch,
+    pub case_sensitive: bool,
+    /// 1 searches the direct children only
+    pub max_depth: Option<usize>,
+    /// Extensions without the dot, compared case-insensitively
+    pub extensions: Vec<String>,
+    pub min_size: Option<u64>,
+    pub max_size: Option<u64>,
+    /// RFC 3339 timestamps
+    pub modified_after: Option<String>,
+    pub modified_before: Option<String>,
+    pub kind: Option<EntryKind>,
+}
+
+enum NameMatcher {
+    Substring { needle: String, case_sensitive: bool },
+    Glob(GlobMatcher),
+    Regex(Regex),
+}
+
+impl NameMatcher {
+    fn is_match(&self, name: &str) -> bool {
+        match self {
+            NameMatcher::Substring { needle, case_sensitive: true } => name.contains(needle.as_str()),
+            NameMatcher::Substring { needle, case_sensitive: false } => {
+                name.to_lowercase().contains(needle.as_str())
+            }
+            NameMatcher::Glob(glob) => glob.is_match(name),
+            NameMatcher::Regex(regex) => regex.is_match(name),
+        }
+    }
+}
+
+/// A query and its filters, validated and ready to test entries against.
+pub struct Searcher {
+    name: NameMatcher,
+    filters: SearchFilters,
+    extensions: Vec<String>,
+    modified_after: Option<DateTime<Utc>>,
+    modified_before: Option<DateTime<Utc>>,
+}
+
+impl Searcher {
+    pub fn new(query: &str, filters: SearchFilters) -> Result<Self> {
+        let name = match filters.match_mode {
+            NameMatch::Substring => NameMatcher::Substring {
+                needle: if filters.case_sensitive { query.to_string() } else { query.to_lowercase() },
+                case_sensitive: filters.case_sensitive,
+            },
+            NameMatch::Glob => NameMatcher::Glob(
+                GlobBuilder::new(query)
+                    .case_insensitive(!filters.case_sensitive)
+                    .build()
+                    .map_err(|e| AppError::InvalidQuery(e.to_string()))?
+                    .compile_matcher(),
+            ),
+            NameMatch::Regex => NameMatcher::Regex(
+                RegexBuilder::new(query)
+                    .case_insensitive(!filters.case_sensitive)
+                    .build()
+                    .map_err(|e| AppError::InvalidQuery(e.to_string()))?,
+            ),
+        };
+
+        let parse_date = |value: &Option<String>| -> Result<Option<DateTime<Utc>>> {
+            value
+                .as_deref()
+                .map(|v| {
+                    DateTime::parse_from_rfc3339(v)
+                        .map(|t| t.with_timezone(&Utc))
+                        .map_err(|e| AppError::InvalidQuery(format!("{}: {}", v, e)))
+                })
+                .transpose()
+        };
+
+        Ok(Searcher {
+            name,
+            extensions: filters.extensions.iter().map(|e| e.trim_start_matches('.').to_lowercase()).collect(),
+            modified_after: parse_date(&filters.modified_after)?,
+            modified_before: parse_date(&filters.modified_before)?,
+            filters,
+        })
+    }
+
+    fn matches(&self, item: &FileItem) -> bool {
+        let f = &self.filters;
+
+        match f.kind {
+            Some(EntryKind::File) if item.is_dir => return false,
+            Some(EntryKind::Dir) if !item.is_dir => return false,
+            _ => {}
+        }
+
+        if !self.extensions.is_empty() {
+            let ext = item.ext.as_deref().map(str::to_lowercase);
+            if !ext.is_some_and(|ext| self.extensions.contains(&ext)) {
+                return false;
+            }
+        }
+
+        // Directory sizes aren't meaningful here, so size limits only apply to files
+        if !item.is_dir
+            && (f.min_size.is_some_and(|min| item.size < min) || f.max_size.is_some_and(|max| item.size > max))
+        {
+            return false;
+        }
+
+        if self.modified_after.is_some() || self.modified_before.is_some() {
+            let modified = match DateTime::parse_from_rfc3339(&item.modified) {
+                Ok(t) => t.with_timezone(&Utc),
+                Err(_) => return false,
+            };
+            if self.modified_after.is_some_and(|after| modified < after)
+                || self.modified_before.is_some_and(|before| modified > before)
+            {
+                return false;
+            }
+        }
+
+        self.name.is_match(&item.name)
+    }
+
+    /// Walks `root` through its handle without following symlinks and hands
+    /// matches to `on_chunk` in batches as they are found. Unreadable entries
+    /// are skipped. Returns the number of matches.
+    pub fn search(&self, root: &Location, progress: &dyn Progress, on_chunk: &mut dyn FnMut(Vec<FileItem>)) -> usize {
+        let mut found = 0;
+        let mut chunk = Vec::new();
+        let mut last_flush = Instant::now();
+
+        for (entry, _) in root.walk(self.filters.max_depth) {
+            if progress.is_cancelled() {
+                break;
+            }
+            progress.item_started(&entry.path());
+
+            if let Some(item) = FileItem::from_location(&entry).ok().filter(|item| self.matches(item)) {
+                chunk.push(item);
+                found += 1;
+            }
+
+            if chunk.len() >= CHUNK_SIZE || (!chunk.is_empty() && last_flush.elapsed() >= CHUNK_INTERVAL) {
+                on_chunk(std::mem::take(&mut chunk));
+                last_flush = Instant::now();
+            }
+        }
+
+        if !chunk.is_empty() {
+            on_chunk(chunk);
+        }
+
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::operations::NoProgress;
+    use std::fs;
+    use std::path::Path;
+
+    fn run(root: &Path, query: &str, filters: SearchFilters) -> Vec<String> {
+        let mut names = Vec::new();
+        Searcher::new(query, filters)
+            .unwrap()
+            .search(&Location::ambient(root).unwrap(), &NoProgress, &mut |chunk| names.extend(chunk.into_iter().map(|i| i.name)));
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_search_filters() {
+        let root = std::env::temp_dir().join(format!("fm-search-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(root.join("docs/old")).unwrap();
+        fs::write(root.join("notes.txt"), "x").unwrap();
+        fs::write(root.join("docs/Report.PDF"), "xxxxxxxxxx").unwrap();
+        fs::write(root.join("docs/old/report-2019.txt"), "").unwrap();
+
+        assert_eq!(run(&root, "report", SearchFilters::default()), vec!["Report.PDF", "report-2019.txt"]);
+
+        let shallow = SearchFilters { max_depth: Some(2), ..Default::default() };
+        assert_eq!(run(&root, "report", shallow), vec!["Report.PDF"]);
+
+        let glob = SearchFilters { match_mode: NameMatch::Glob, ..Default::default() };
+        assert_eq!(run(&root, "*.txt", glob), vec!["notes.txt", "report-2019.txt"]);
+
+        let regex = SearchFilters { match_mode: NameMatch::Regex, case_sensitive: true, ..Default::default() };
+        assert_eq!(run(&root, r"^report-\d+", regex), vec!["report-2019.txt"]);
+
+        let sized = SearchFilters { extensions: vec!["pdf".to_string()], min_size: Some(5), ..Default::default() };
+        assert_eq!(run(&root, "", sized), vec!["Report.PDF"]);
+
+        let dirs = SearchFilters { kind: Some(EntryKind::Dir), ..Default::default() };
+        assert_eq!(run(&root, "", dirs), vec!["docs", "old"]);
+
+        let bad = SearchFilters { match_mode: NameMatch::Regex, ..Default::default() };
+        assert!(matches!(Searcher::new("(", bad), Err(AppError::InvalidQuery(_))));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
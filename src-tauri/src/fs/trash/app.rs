@@ -0,0 +1,369 @@
+use super::{TrashBackend, TrashBackendKind, TrashItem};
+use crate::error::{AppError, Result};
+use crate::fs::conflict::{resolve_destination, ConflictPolicy, Destination};
+use crate::fs::operations::{move_entry, tree_size, BatchItemResult, BatchResult, NoProgress, Progress, SymlinkPolicy};
+use crate::fs::sandbox::{Location, Sandbox};
+use chrono::{NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+const METADATA_FILE: &str = ".metadata.json";
+
+/// Contents of the `.metadata.json` written into every batch folder.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrashMetadata {
+    pub batch_id: String,
+    pub timestamp: String,
+    pub items: Vec<TrashMetadataItem>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum TrashMetadataItem {
+    Recorded {
+        original_path: String,
+        trash_path: String,
+    },
+    /// Older folders only list the original path; the item sits in the
+    /// folder under its original name.
+    Legacy(String),
+}
+
+impl TrashMetadataItem {
+    /// `(original_path, trash_path)` of the item inside `folder`.
+    pub fn locate(&self, folder: &Path) -> (PathBuf, PathBuf) {
+        match self {
+            TrashMetadataItem::Recorded { original_path, trash_path } => {
+                (PathBuf::from(original_path), PathBuf::from(trash_path))
+            }
+            TrashMetadataItem::Legacy(original_path) => {
+                let original = PathBuf::from(original_path);
+                let trash_path = folder.join(original.file_name().unwrap_or_default());
+                (original, trash_path)
+            }
+        }
+    }
+}
+
+fn read_metadata(folder: &Path) -> Option<TrashMetadata> {
+    let content = fs::read_to_string(folder.join(METADATA_FILE)).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+/// Trash kept in the app's data directory, one folder per delete batch.
+#[derive(Clone)]
+pub struct AppTrash {
+    trash_root: PathBuf,
+}
+
+impl AppTrash {
+    pub fn new(app_root: &Path) -> Self {
+        let trash_root = app_root.join(".trash");
+        AppTrash { trash_root }
+    }
+
+    /// Creates the folder of a new batch and returns it with its timestamp.
+    fn batch_folder(&self, batch_id: &str) -> Result<(PathBuf, String)> {
+        let timestamp = Utc::now().format("%Y%m%d_%H%M%S").to_string();
+        // One folder per batch, so deletes in the same second never share one
+        let trash_dir = self.trash_root.join(format!("{}_{}", timestamp, batch_id));
+        fs::create_dir_all(&trash_dir)?;
+        Ok((trash_dir, timestamp))
+    }
+}
+
+/// Records where the items of a batch came from, for restores and undo.
+fn write_metadata(trash_dir: &Path, metadata: &TrashMetadata) -> Result<()> {
+    fs::write(trash_dir.join(METADATA_FILE), serde_json::to_string_pretty(metadata)?)?;
+    Ok(())
+}
+
+impl TrashBackend for AppTrash {
+    fn kind(&self) -> TrashBackendKind {
+        TrashBackendKind::App
+    }
+
+    fn owns(&self, trash_path: &Path) -> bool {
+        trash_path.starts_with(&self.trash_root)
+    }
+
+    fn soft_delete(
+        &self,
+        sandbox: &Sandbox,
+        paths: Vec<PathBuf>,
+        policy: ConflictPolicy,
+        links: SymlinkPolicy,
+        progress: &dyn Progress,
+    ) -> Result<BatchResult> {
+        let batch_id = Uuid::new_v4().to_string();
+        let (trash_dir, timestamp) = self.batch_folder(&batch_id)?;
+        let slots = Location::ambient(&trash_dir)?;
+        // Items already in the trash are never replaced
+        let policy = match policy {
+            ConflictPolicy::Overwrite => ConflictPolicy::KeepBoth,
+            other => other,
+        };
+
+        let mut results = Vec::new();
+
+        progress.begin(paths.len(), 0);
+
+        for path in paths {
+            if progress.is_cancelled() {
+                results.push(BatchItemResult::cancelled(&path));
+                continue;
+            }
+            progress.item_started(&path);
+
+            let item = match super::locate_item(sandbox, links, &path) {
+                Ok(item) => item,
+                Err(result) => {
+                    results.push(*result);
+                    progress.item_finished();
+                    continue;
+                }
+            };
+            // A followed link trashes its target
+            let path = item.path();
+
+            let file_name = match item.file_name() {
+                Some(name) => name.to_owned(),
+                None => {
+                    results.push(BatchItemResult::failed(&path, AppError::InvalidPath("Invalid file name".to_string())));
+                    progress.item_finished();
+                    continue;
+                }
+            };
+            
+            let trash_path = slots.join(file_name);
+
+            let result = match resolve_destination(trash_path, policy) {
+                Ok(Destination::Use(target, resolution)) => match move_entry(&item, &target, progress) {
+                    Ok(_) => BatchItemResult::done(&path, &target.path(), resolution),
+                    Err(AppError::Cancelled(_)) => BatchItemResult::cancelled(&path),
+                    Err(e) => BatchItemResult::failed(&path, e),
+                },
+                Ok(Destination::Replace(_)) => unreachable!("Overwrite is mapped to KeepBoth above"),
+                Ok(Destination::Skip) => BatchItemResult::skipped(&path),
+                Ok(Destination::Fail(error)) => BatchItemResult::failed(&path, error),
+                Err(e) => BatchItemResult::failed(&path, e),
+            };
+
+            results.push(result);
+            progress.item_finished();
+        }
+
+        // Store metadata for undo
+        let metadata = TrashMetadata {
+            batch_id: batch_id.clone(),
+            timestamp,
+            items: results
+                .iter()
+                .filter(|r| r.was_applied())
+                .filter_map(|r| {
+                    Some(TrashMetadataItem::Recorded {
+                        original_path: r.path.clone(),
+                        trash_path: r.dest_path.clone()?,
+                    })
+                })
+                .collect(),
+        };
+        write_metadata(&trash_dir, &metadata)?;
+
+        let mut batch = BatchResult::from_results(batch_id, results);
+        batch.cancelled = progress.is_cancelled();
+        Ok(batch)
+    }
+
+    fn trash_entry(&self, item: &Location) -> Result<PathBuf> {
+        let batch_id = Uuid::new_v4().to_string();
+        let (trash_dir, timestamp) = self.batch_folder(&batch_id)?;
+        let file_name = item
+            .file_name()
+            .ok_or_else(|| AppError::InvalidPath("Invalid file name".to_string()))?;
+        let slot = Location::ambient(&trash_dir)?.join(file_name);
+
+        if let Err(e) = move_entry(item, &slot, &NoProgress) {
+            let _ = fs::remove_dir_all(&trash_dir);
+            return Err(e);
+        }
+
+        let trash_path = slot.path();
+        write_metadata(&trash_dir, &TrashMetadata {
+            batch_id,
+            timestamp,
+            items: vec![TrashMetadataItem::Recorded {
+                original_path: item.path().to_string_lossy().to_string(),
+                trash_path: trash_path.to_string_lossy().to_string(),
+            }],
+        })?;
+        Ok(trash_path)
+    }
+
+    /// Moves a trashed item back to where it came from. The batch folder and
+    /// its metadata stay, so the item can be put back by a redo.
+    fn restore_item(&self, trash_path: &Path, original: &Location) -> Result<()> {
+        if fs::symlink_metadata(trash_path).is_err() {
+            return Err(AppError::UndoFailed(format!(
+                "{} is no longer in the trash",
+                original.path().display()
+            )));
+        }
+
+        if original.exists() {
+            return Err(AppError::FileExists(original.path().to_string_lossy().to_string()));
+        }
+
+        // Ensure parent directory exists
+        if let Some(parent) = original.parent() {
+            parent.create_dir_all()?;
+        }
+
+        move_entry(&Location::ambient(trash_path)?, original, &NoProgress)
+    }
+
+    /// Puts a restored item back into the trash slot it was restored from.
+    fn retrash_item(&self, item: &Location, trash_path: &Path, _batch_id: &str) -> Result<()> {
+        if fs::symlink_metadata(trash_path).is_ok() {
+            return Err(AppError::FileExists(trash_path.to_string_lossy().to_string()));
+        }
+
+        if let Some(parent) = trash_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        move_entry(item, &Location::ambient(trash_path)?, &NoProgress)
+    }
+
+    /// Trash location of an item whose log row predates recorded trash
+    /// paths, looked up through the metadata of the batch that deleted it.
+    fn find_trash_path(&self, batch_id: &str, original_path: &Path) -> Option<PathBuf> {
+        fs::read_dir(&self.trash_root)
+            .ok()?
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter_map(|folder| {
+                let metadata = read_metadata(&folder)?;
+                (metadata.batch_id == batch_id).then_some((folder, metadata))
+            })
+            .find_map(|(folder, metadata)| {
+                metadata
+                    .items
+                    .iter()
+                    .map(|item| item.locate(&folder))
+                    .find(|(original, _)| original == original_path)
+                    .map(|(_, trash_path)| trash_path)
+            })
+    }
+
+    /// Items that were restored or purged are left out even though their
+    /// metadata remains.
+    fn list_items(&self) -> Result<Vec<TrashItem>> {
+        let mut items = Vec::new();
+
+        let folders = match fs::read_dir(&self.trash_root) {
+            Ok(entries) => entries,
+            Err(_) => return Ok(items),
+        };
+
+        for folder in folders.filter_map(|e| e.ok()).map(|e| e.path()) {
+            let metadata = match read_metadata(&folder) {
+                Some(metadata) => metadata,
+                None => continue,
+            };
+            let folder_name = folder.file_name().unwrap_or_default().to_string_lossy().to_string();
+            let deleted_at = NaiveDateTime::parse_from_str(&metadata.timestamp, "%Y%m%d_%H%M%S")
+                .map(|t| t.and_utc().to_rfc3339())
+                .unwrap_or_else(|_| metadata.timestamp.clone());
+
+            for item in &metadata.items {
+                let (original, trash_path) = item.locate(&folder);
+                let entry = match fs::symlink_metadata(&trash_path) {
+                    Ok(entry) => entry,
+                    Err(_) => continue,
+                };
+                let name = trash_path.file_name().unwrap_or_default().to_string_lossy().to_string();
+
+                items.push(TrashItem {
+                    id: format!("{}/{}", folder_name, name),
+                    name: original.file_name().unwrap_or_default().to_string_lossy().to_string(),
+                    original_path: original.to_string_lossy().to_string(),
+                    trash_path: trash_path.to_string_lossy().to_string(),
+                    is_dir: entry.is_dir(),
+                    size: tree_size(&trash_path),
+                    deleted_at: deleted_at.clone(),
+                    batch_id: metadata.batch_id.clone(),
+                    backend: TrashBackendKind::App,
+                });
+            }
+        }
+
+        Ok(items)
+    }
+
+    /// Permanently deletes a trash item, dropping its batch folder once nothing is left in it.
+    fn purge_item(&self, item: &TrashItem) -> Result<()> {
+        let trash_path = PathBuf::from(&item.trash_path);
+        Location::ambient(&trash_path)?.remove()?;
+
+        if let Some(folder) = trash_path.parent() {
+            let only_metadata = fs::read_dir(folder)
+                .map(|entries| entries.filter_map(|e| e.ok()).all(|e| e.file_name() == METADATA_FILE))
+                .unwrap_or(false);
+            if only_metadata {
+                fs::remove_dir_all(folder)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_batch_folders_and_legacy_metadata() {
+        let root = std::env::temp_dir().join(format!("fm-app-trash-{}", Uuid::new_v4()));
+        let trash = AppTrash::new(&root);
+        let original = root.join("docs").join("a.txt");
+        fs::create_dir_all(original.parent().unwrap()).unwrap();
+
+        // Back-to-back deletes of the same name get a folder each
+        let mut folders = Vec::new();
+        for _ in 0..2 {
+            fs::write(&original, "hello").unwrap();
+            let batch = trash
+                .soft_delete(&Sandbox::new(&[], false), vec![original.clone()], ConflictPolicy::default(), SymlinkPolicy::Preserve, &NoProgress)
+                .unwrap();
+            let slot = PathBuf::from(batch.results[0].dest_path.clone().unwrap());
+            assert_eq!(slot.file_name().unwrap(), "a.txt");
+            let folder = slot.parent().unwrap().to_path_buf();
+            assert_eq!(read_metadata(&folder).unwrap().batch_id, batch.batch_id);
+            folders.push(folder);
+        }
+        assert_ne!(folders[0], folders[1]);
+
+        // Folders written before trash paths were recorded only list the original
+        let legacy = root.join(".trash").join("20200101_000000");
+        fs::create_dir_all(&legacy).unwrap();
+        fs::write(legacy.join("b.txt"), "old").unwrap();
+        let metadata = TrashMetadata {
+            batch_id: "legacy".to_string(),
+            timestamp: "20200101_000000".to_string(),
+            items: vec![TrashMetadataItem::Legacy(root.join("docs/b.txt").to_string_lossy().to_string())],
+        };
+        fs::write(legacy.join(METADATA_FILE), serde_json::to_string(&metadata).unwrap()).unwrap();
+
+        assert_eq!(trash.find_trash_path("legacy", &root.join("docs/b.txt")), Some(legacy.join("b.txt")));
+        assert_eq!(trash.find_trash_path("legacy", &original), None);
+        let items = trash.list_items().unwrap();
+        assert_eq!(items.len(), 3);
+        assert!(items.iter().any(|i| i.batch_id == "legacy" && i.deleted_at.starts_with("2020-01-01")));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
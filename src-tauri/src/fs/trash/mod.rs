@@ -0,0 +1,366 @@
+mod app;
+#[cfg(target_os = "linux")]
+mod xdg;
+
+use crate::error::{AppError, Result};
+use crate::fs::conflict::{replace, resolve_destination, ConflictPolicy, Destination, SetAside};
+use crate::fs::operations::{BatchItemResult, BatchResult, Progress, SymlinkPolicy};
+use crate::fs::sandbox::{Location, Sandbox};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+pub use app::AppTrash;
+
+/// Which trash new deletions go to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TrashBackendKind {
+    /// The app's own `.trash` folder in its data directory
+    #[default]
+    App,
+    /// The desktop trash as defined by the freedesktop.org Trash specification
+    Xdg,
+}
+
+/// A place deleted items are moved to and restored from.
+pub trait TrashBackend: Send + Sync {
+    fn kind(&self) -> TrashBackendKind;
+
+    /// Whether `trash_path` is an item slot of this trash.
+    fn owns(&self, trash_path: &Path) -> bool;
+
+    /// Trashes `paths`, located in `sandbox` one at a time. Selected
+    /// symlinks are resolved per `links` first.
+    fn soft_delete(
+        &self,
+        sandbox: &Sandbox,
+        paths: Vec<PathBuf>,
+        policy: ConflictPolicy,
+        links: SymlinkPolicy,
+        progress: &dyn Progress,
+    ) -> Result<BatchResult>;
+
+    /// Trashes a single entry in a batch of its own and returns its slot.
+    fn trash_entry(&self, item: &Location) -> Result<PathBuf>;
+
+    /// Moves a trashed item back to `original`, which must be vacant.
+    fn restore_item(&self, trash_path: &Path, original: &Location) -> Result<()>;
+
+    /// Puts a restored item back into the trash slot it was restored from.
+    fn retrash_item(&self, item: &Location, trash_path: &Path, batch_id: &str) -> Result<()>;
+
+    /// Everything this app put in the trash that is still there, in no
+    /// particular order.
+    fn list_items(&self) -> Result<Vec<TrashItem>>;
+
+    /// Permanently deletes a trash item.
+    fn purge_item(&self, item: &TrashItem) -> Result<()>;
+
+    /// Trash location of an item whose log row predates recorded trash paths.
+    fn find_trash_path(&self, _batch_id: &str, _original_path: &Path) -> Option<PathBuf> {
+        None
+    }
+}
+
+/// The entry to trash for the selected `path`, or the result to report
+/// instead when it is outside the roots or a link that is skipped or can't
+/// be followed.
+fn locate_item(sandbox: &Sandbox, links: SymlinkPolicy, path: &Path) -> std::result::Result<Location, Box<BatchItemResult>> {
+    match sandbox.locate(path).and_then(|location| links.resolve_item(&location)) {
+        Ok(Some(location)) => Ok(location),
+        Ok(None) => Err(Box::new(BatchItemResult::skipped_with(path, "Skipped: symbolic link"))),
+        Err(e) => Err(Box::new(BatchItemResult::failed(path, e))),
+    }
+}
+
+/// An item currently sitting in the trash.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrashItem {
+    /// Opaque id, stable for as long as the item is in the trash
+    pub id: String,
+    pub name: String,
+    pub original_path: String,
+    pub trash_path: String,
+    #[serde(rename = "isDir")]
+    pub is_dir: bool,
+    pub size: u64,
+    pub deleted_at: String,
+    pub batch_id: String,
+    pub backend: TrashBackendKind,
+}
+
+/// Limits enforced by the trash sweeper. `None` means unlimited.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TrashRetention {
+    pub max_age_days: Option<u32>,
+    pub max_total_bytes: Option<u64>,
+    pub max_items: Option<usize>,
+}
+
+impl TrashRetention {
+    /// Items of the batches that have to go to satisfy the limits, each with
+    /// the reason. Whole batches are purged, oldest first.
+    pub fn select_expired(&self, items: Vec<TrashItem>) -> Vec<(TrashItem, String)> {
+        let mut batches: Vec<(String, Vec<TrashItem>)> = Vec::new();
+        for item in items {
+            match batches.iter_mut().find(|(id, _)| *id == item.batch_id) {
+                Some((_, batch)) => batch.push(item),
+                None => batches.push((item.batch_id.clone(), vec![item])),
+            }
+        }
+        batches.sort_by(|(_, a), (_, b)| a[0].deleted_at.cmp(&b[0].deleted_at));
+
+        let mut total_bytes: u64 = batches.iter().flat_map(|(_, b)| b).map(|i| i.size).sum();
+        let mut total_items: usize = batches.iter().map(|(_, b)| b.len()).sum();
+        let cutoff = self
+            .max_age_days
+            .map(|days| Utc::now() - chrono::Duration::days(days as i64));
+
+        let mut expired = Vec::new();
+        for (_, batch) in batches {
+            let deleted_at = DateTime::parse_from_rfc3339(&batch[0].deleted_at).ok();
+
+            let reason = if matches!((cutoff, deleted_at), (Some(cutoff), Some(at)) if at < cutoff) {
+                format!("Older than {} days", self.max_age_days.unwrap_or_default())
+            } else if self.max_total_bytes.is_some_and(|max| total_bytes > max) {
+                "Trash size quota exceeded".to_string()
+            } else if self.max_items.is_some_and(|max| total_items > max) {
+                "Trash item quota exceeded".to_string()
+            } else {
+                // Everything newer is within the age limit and the quotas hold
+                break;
+            };
+
+            total_bytes -= batch.iter().map(|i| i.size).sum::<u64>();
+            total_items -= batch.len();
+            expired.extend(batch.into_iter().map(|item| (item, reason.clone())));
+        }
+
+        expired
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrashUsage {
+    pub total_bytes: u64,
+    pub item_count: usize,
+    pub batch_count: usize,
+    pub oldest_deleted_at: Option<String>,
+}
+
+/// Sends new deletions to the selected backend, and everything else to the
+/// backend actually holding the item, so switching backends never strands
+/// items that are already in a trash.
+#[derive(Clone)]
+pub struct TrashManager {
+    backends: Vec<Arc<dyn TrashBackend>>,
+    active: Arc<RwLock<TrashBackendKind>>,
+}
+
+impl TrashManager {
+    pub fn new(app_root: &Path) -> Self {
+        #[allow(unused_mut)]
+        let mut backends: Vec<Arc<dyn TrashBackend>> = vec![Arc::new(AppTrash::new(app_root))];
+
+        #[cfg(target_os = "linux")]
+        if let Some(xdg) = xdg::XdgTrash::from_env() {
+            backends.push(Arc::new(xdg));
+        }
+
+        TrashManager {
+            backends,
+            active: Arc::new(RwLock::new(TrashBackendKind::default())),
+        }
+    }
+
+    pub fn backend(&self) -> TrashBackendKind {
+        *self.active.read().unwrap()
+    }
+
+    pub fn set_backend(&self, kind: TrashBackendKind) -> Result<()> {
+        if !self.backends.iter().any(|b| b.kind() == kind) {
+            return Err(AppError::NotAllowed(format!(
+                "Trash backend {:?} is not available on this system",
+                kind
+            )));
+        }
+
+        *self.active.write().unwrap() = kind;
+        Ok(())
+    }
+
+    fn active(&self) -> &dyn TrashBackend {
+        let kind = self.backend();
+        self.backends
+            .iter()
+            .find(|b| b.kind() == kind)
+            .unwrap_or(&self.backends[0])
+            .as_ref()
+    }
+
+    fn owner(&self, trash_path: &Path) -> Result<&dyn TrashBackend> {
+        self.backends
+            .iter()
+            .find(|b| b.owns(trash_path))
+            .map(|b| b.as_ref())
+            .ok_or_else(|| AppError::InvalidPath(format!("{} is not in a trash", trash_path.display())))
+    }
+
+    /// Trashes `paths` with the active backend.
+    pub fn soft_delete(
+        &self,
+        sandbox: &Sandbox,
+        paths: Vec<PathBuf>,
+        policy: ConflictPolicy,
+        links: SymlinkPolicy,
+        progress: &dyn Progress,
+    ) -> Result<BatchResult> {
+        self.active().soft_delete(sandbox, paths, policy, links, progress)
+    }
+
+    pub fn restore_item(&self, trash_path: &Path, original: &Location) -> Result<()> {
+        self.owner(trash_path)?.restore_item(trash_path, original)
+    }
+
+    pub fn retrash_item(&self, item: &Location, trash_path: &Path, batch_id: &str) -> Result<()> {
+        self.owner(trash_path)?.retrash_item(item, trash_path, batch_id)
+    }
+
+    pub fn find_trash_path(&self, batch_id: &str, original_path: &Path) -> Option<PathBuf> {
+        self.backends
+            .iter()
+            .find_map(|b| b.find_trash_path(batch_id, original_path))
+    }
+
+    /// Items of every available trash, newest deletions first.
+    pub fn list_items(&self) -> Result<Vec<TrashItem>> {
+        let mut items = Vec::new();
+        for backend in &self.backends {
+            items.extend(backend.list_items()?);
+        }
+
+        items.sort_by(|a, b| b.deleted_at.cmp(&a.deleted_at));
+        Ok(items)
+    }
+
+    pub fn usage(&self) -> Result<TrashUsage> {
+        let items = self.list_items()?;
+        let mut batches: Vec<&str> = items.iter().map(|i| i.batch_id.as_str()).collect();
+        batches.sort();
+        batches.dedup();
+
+        Ok(TrashUsage {
+            total_bytes: items.iter().map(|i| i.size).sum(),
+            item_count: items.len(),
+            batch_count: batches.len(),
+            oldest_deleted_at: items.iter().map(|i| i.deleted_at.clone()).min(),
+        })
+    }
+
+    /// Looks up a trash item by the id handed out by `list_items`.
+    pub fn get_item(&self, id: &str) -> Result<TrashItem> {
+        self.list_items()?
+            .into_iter()
+            .find(|item| item.id == id)
+            .ok_or_else(|| AppError::FileNotFound(format!("Trash item {}", id)))
+    }
+
+    /// Moves a trash item back to its original location, which has to be
+    /// inside `sandbox`.
+    pub fn restore_trash_item(&self, sandbox: &Sandbox, item: &TrashItem, policy: ConflictPolicy) -> BatchItemResult {
+        let trash_path = PathBuf::from(&item.trash_path);
+        let original = match sandbox.locate(Path::new(&item.original_path)) {
+            Ok(original) => original,
+            Err(e) => return BatchItemResult::failed(&trash_path, e),
+        };
+
+        let restore = |target: &Location| {
+            target
+                .parent()
+                .map_or(Ok(()), |parent| parent.create_dir_all())
+                .and_then(|_| self.restore_item(&trash_path, target))
+        };
+
+        match resolve_destination(original, policy) {
+            Ok(Destination::Use(target, resolution)) => match restore(&target) {
+                Ok(_) => BatchItemResult::done(&trash_path, &target.path(), resolution),
+                Err(e) => BatchItemResult::failed(&trash_path, e),
+            },
+            Ok(Destination::Replace(target)) => match replace(&target, self, || restore(&target)) {
+                Ok(replaced) => BatchItemResult::replaced(&trash_path, &target.path(), &replaced),
+                Err(e) => BatchItemResult::failed(&trash_path, e),
+            },
+            Ok(Destination::Skip) => BatchItemResult::skipped(&trash_path),
+            Ok(Destination::Fail(error)) => BatchItemResult::failed(&trash_path, error),
+            Err(e) => BatchItemResult::failed(&trash_path, e),
+        }
+    }
+
+    pub fn purge_item(&self, item: &TrashItem) -> Result<()> {
+        self.owner(Path::new(&item.trash_path))?.purge_item(item)
+    }
+}
+
+/// Entries an overwrite replaces go to the active trash, each in a batch
+/// of its own.
+impl SetAside for TrashManager {
+    fn set_aside(&self, entry: &Location) -> Result<PathBuf> {
+        self.active().trash_entry(entry)
+    }
+
+    fn put_back(&self, set_aside: &Path, entry: &Location) -> Result<()> {
+        self.restore_item(set_aside, entry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(batch_id: &str, days_ago: i64, size: u64) -> TrashItem {
+        TrashItem {
+            id: format!("{}/item", batch_id),
+            name: "item".to_string(),
+            original_path: "/tmp/item".to_string(),
+            trash_path: format!("/trash/{}/item", batch_id),
+            is_dir: false,
+            size,
+            deleted_at: (Utc::now() - chrono::Duration::days(days_ago)).to_rfc3339(),
+            batch_id: batch_id.to_string(),
+            backend: TrashBackendKind::App,
+        }
+    }
+
+    fn expired_batches(retention: &TrashRetention, items: Vec<TrashItem>) -> Vec<String> {
+        let mut batches: Vec<String> = retention
+            .select_expired(items)
+            .into_iter()
+            .map(|(item, _)| item.batch_id)
+            .collect();
+        batches.dedup();
+        batches
+    }
+
+    #[test]
+    fn test_select_expired_oldest_batches_first() {
+        let items = vec![
+            item("new", 1, 100),
+            item("old", 40, 100),
+            item("old", 40, 100),
+            item("mid", 10, 100),
+        ];
+
+        let by_age = TrashRetention { max_age_days: Some(30), ..Default::default() };
+        assert_eq!(expired_batches(&by_age, items.clone()), vec!["old"]);
+
+        let by_size = TrashRetention { max_total_bytes: Some(150), ..Default::default() };
+        assert_eq!(expired_batches(&by_size, items.clone()), vec!["old", "mid"]);
+
+        let by_count = TrashRetention { max_items: Some(2), ..Default::default() };
+        assert_eq!(expired_batches(&by_count, items.clone()), vec!["old"]);
+
+        assert!(expired_batches(&TrashRetention::default(), items).is_empty());
+    }
+}
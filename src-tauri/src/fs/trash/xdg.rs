@@ -0,0 +1,535 @@
+//! The desktop trash as described by the freedesktop.org Trash specification:
+//! <https://specifications.freedesktop.org/trash-spec/latest/>
+//!
+//! Every trash directory holds the trashed items in `files/` and one
+//! `info/<name>.trashinfo` per item recording where it came from and when.
+//! Items on the home volume go to `$XDG_DATA_HOME/Trash`; items on other
+//! mounts go to `$topdir/.Trash/$uid` or `$topdir/.Trash-$uid`.
+//!
+//! The same directories hold what other programs trashed. Info files written
+//! here carry an extra `X-FileManager-Batch` key, and only items with it are
+//! listed, so retention and emptying the trash never touch anything else.
+
+use super::{TrashBackend, TrashBackendKind, TrashItem};
+use crate::error::{AppError, Result};
+use crate::fs::conflict::{numbered_path, ConflictPolicy, ConflictResolution};
+use crate::fs::operations::{move_entry, tree_size, BatchItemResult, BatchResult, NoProgress, Progress, SymlinkPolicy};
+use crate::fs::sandbox::{Location, Sandbox};
+use chrono::{Local, NaiveDateTime, TimeZone, Utc};
+use std::ffi::OsString;
+use std::fs::{self, DirBuilder, File, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+const INFO_EXT: &str = ".trashinfo";
+/// Key marking an info file as written by this app, holding the batch id.
+const BATCH_KEY: &str = "X-FileManager-Batch";
+const DATE_FORMAT: &str = "%Y-%m-%dT%H:%M:%S";
+
+/// Bit marking a shared `.Trash` directory as usable, see the spec.
+const STICKY_BIT: u32 = 0o1000;
+
+#[derive(Clone)]
+pub struct XdgTrash {
+    home_trash: PathBuf,
+    uid: u32,
+    /// Top directories whose trash is searched besides the home trash
+    mount_points: fn() -> Vec<PathBuf>,
+}
+
+impl XdgTrash {
+    /// The trash of the current user, or `None` without a home directory.
+    pub fn from_env() -> Option<Self> {
+        // dirs honours $XDG_DATA_HOME and falls back to ~/.local/share
+        let home_trash = dirs::data_dir()?.join("Trash");
+        // SAFETY: getuid has no preconditions and cannot fail
+        let uid = unsafe { libc::getuid() };
+        Some(XdgTrash { home_trash, uid, mount_points })
+    }
+
+    /// Trash directory for an item, created if necessary. Items on another
+    /// mount use that mount's trash so nothing has to be copied, falling back
+    /// to the home trash when the mount has none and one cannot be made.
+    fn trash_dir_for(&self, item: &Location) -> Result<PathBuf> {
+        create_trash_dir(&self.home_trash)?;
+
+        let device = cap_std::fs::MetadataExt::dev(&item.symlink_metadata()?);
+        if fs::metadata(&self.home_trash)?.dev() == device {
+            return Ok(self.home_trash.clone());
+        }
+
+        let path = item.path();
+        let top_dir = match path.parent().map(fs::canonicalize) {
+            Some(Ok(parent)) => mount_point(&parent, device),
+            _ => return Ok(self.home_trash.clone()),
+        };
+
+        let shared = top_dir.join(".Trash");
+        let shared_usable = fs::symlink_metadata(&shared)
+            .map(|m| m.is_dir() && m.mode() & STICKY_BIT != 0)
+            .unwrap_or(false);
+        if shared_usable {
+            let dir = shared.join(self.uid.to_string());
+            if create_trash_dir(&dir).is_ok() {
+                return Ok(dir);
+            }
+        }
+
+        let dir = top_dir.join(format!(".Trash-{}", self.uid));
+        if create_trash_dir(&dir).is_ok() {
+            return Ok(dir);
+        }
+
+        Ok(self.home_trash.clone())
+    }
+
+    /// The home trash plus the trash of every mount that has one for us.
+    fn trash_dirs(&self) -> Vec<PathBuf> {
+        let mut dirs = vec![self.home_trash.clone()];
+
+        for top_dir in (self.mount_points)() {
+            for dir in [
+                top_dir.join(".Trash").join(self.uid.to_string()),
+                top_dir.join(format!(".Trash-{}", self.uid)),
+            ] {
+                if dir.join("info").is_dir() && !dirs.contains(&dir) {
+                    dirs.push(dir);
+                }
+            }
+        }
+
+        dirs
+    }
+
+    fn is_trash_dir(&self, dir: &Path) -> bool {
+        let name = dir.file_name().unwrap_or_default().to_string_lossy();
+        let parent_name = dir
+            .parent()
+            .and_then(|p| p.file_name())
+            .unwrap_or_default()
+            .to_string_lossy();
+
+        dir == self.home_trash
+            || name == format!(".Trash-{}", self.uid)
+            || (parent_name == ".Trash" && name == self.uid.to_string())
+    }
+
+    /// Reserves a unique name in `trash_dir` by creating its info file, as
+    /// the spec requires, and returns the matching slot in `files/`.
+    fn reserve_slot(&self, trash_dir: &Path, path: &Path, batch_id: &str) -> Result<(PathBuf, ConflictResolution)> {
+        let file_name = path
+            .file_name()
+            .ok_or_else(|| AppError::InvalidPath("Invalid file name".to_string()))?;
+        let wanted = trash_dir.join("files").join(file_name);
+
+        for n in 1.. {
+            let slot = numbered_path(&wanted, n);
+            if fs::symlink_metadata(&slot).is_ok() {
+                continue;
+            }
+
+            match OpenOptions::new().write(true).create_new(true).open(info_path(&slot)) {
+                Ok(mut file) => {
+                    write_info(&mut file, trash_dir, path, batch_id)?;
+                    let resolution = if n == 1 {
+                        ConflictResolution::NoConflict
+                    } else {
+                        ConflictResolution::Renamed
+                    };
+                    return Ok((slot, resolution));
+                }
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        unreachable!("numbered trash slots are unbounded")
+    }
+
+    fn trash_one(&self, item: &Location, batch_id: &str, progress: &dyn Progress) -> Result<(PathBuf, ConflictResolution)> {
+        let trash_dir = self.trash_dir_for(item)?;
+        let (slot, resolution) = self.reserve_slot(&trash_dir, &item.path(), batch_id)?;
+
+        if let Err(e) = Location::ambient(&slot).and_then(|dest| move_entry(item, &dest, progress)) {
+            let _ = fs::remove_file(info_path(&slot));
+            return Err(e);
+        }
+
+        Ok((slot, resolution))
+    }
+
+    /// The item `info_file` describes, `None` if it is unreadable or was
+    /// trashed by another program.
+    fn read_item(&self, trash_dir: &Path, info_file: &Path) -> Option<TrashItem> {
+        let content = fs::read_to_string(info_file).ok()?;
+        let info_name = info_file.file_name()?.to_str()?;
+        let trash_path = trash_dir.join("files").join(info_name.strip_suffix(INFO_EXT)?);
+        let entry = fs::symlink_metadata(&trash_path).ok()?;
+
+        let mut original = None;
+        let mut deletion_date = None;
+        let mut batch_id = None;
+        for line in content.lines().skip_while(|l| l.trim() != "[Trash Info]").skip(1) {
+            if line.starts_with('[') {
+                break;
+            }
+            match line.split_once('=') {
+                Some(("Path", value)) => original = Some(decode_path(value.trim())),
+                Some(("DeletionDate", value)) => deletion_date = Some(value.trim().to_string()),
+                Some((BATCH_KEY, value)) => batch_id = Some(value.trim().to_string()),
+                _ => {}
+            }
+        }
+
+        let batch_id = batch_id?;
+        let original = original?;
+        // Relative paths are relative to the top directory of the mount
+        let original = match top_dir_of(trash_dir) {
+            Some(top_dir) if original.is_relative() => top_dir.join(original),
+            _ => original,
+        };
+        let deletion_date = deletion_date.unwrap_or_default();
+        let deleted_at = NaiveDateTime::parse_from_str(&deletion_date, DATE_FORMAT)
+            .ok()
+            .and_then(|t| Local.from_local_datetime(&t).earliest())
+            .map(|t| t.with_timezone(&Utc).to_rfc3339())
+            .unwrap_or_else(|| deletion_date.clone());
+
+        Some(TrashItem {
+            id: format!("xdg:{}", trash_path.display()),
+            name: original.file_name().unwrap_or_default().to_string_lossy().to_string(),
+            original_path: original.to_string_lossy().to_string(),
+            trash_path: trash_path.to_string_lossy().to_string(),
+            is_dir: entry.is_dir(),
+            size: tree_size(&trash_path),
+            deleted_at,
+            batch_id,
+            backend: TrashBackendKind::Xdg,
+        })
+    }
+}
+
+impl TrashBackend for XdgTrash {
+    fn kind(&self) -> TrashBackendKind {
+        TrashBackendKind::Xdg
+    }
+
+    fn owns(&self, trash_path: &Path) -> bool {
+        let files_dir = match trash_path.parent() {
+            Some(dir) if dir.file_name() == Some("files".as_ref()) => dir,
+            _ => return false,
+        };
+        files_dir.parent().is_some_and(|dir| self.is_trash_dir(dir))
+    }
+
+    /// Names inside the trash are always made unique, as the spec requires,
+    /// so the conflict policy never comes into play.
+    fn soft_delete(
+        &self,
+        sandbox: &Sandbox,
+        paths: Vec<PathBuf>,
+        _policy: ConflictPolicy,
+        links: SymlinkPolicy,
+        progress: &dyn Progress,
+    ) -> Result<BatchResult> {
+        let batch_id = Uuid::new_v4().to_string();
+        let mut results = Vec::new();
+
+        progress.begin(paths.len(), 0);
+
+        for path in paths {
+            if progress.is_cancelled() {
+                results.push(BatchItemResult::cancelled(&path));
+                continue;
+            }
+            progress.item_started(&path);
+
+            let item = match super::locate_item(sandbox, links, &path) {
+                Ok(item) => item,
+                Err(result) => {
+                    results.push(*result);
+                    progress.item_finished();
+                    continue;
+                }
+            };
+            // A followed link trashes its target
+            let path = item.path();
+
+            let result = match self.trash_one(&item, &batch_id, progress) {
+                Ok((slot, resolution)) => BatchItemResult::done(&path, &slot, resolution),
+                Err(AppError::Cancelled(_)) => BatchItemResult::cancelled(&path),
+                Err(e) => BatchItemResult::failed(&path, e),
+            };
+
+            results.push(result);
+            progress.item_finished();
+        }
+
+        let mut batch = BatchResult::from_results(batch_id, results);
+        batch.cancelled = progress.is_cancelled();
+        Ok(batch)
+    }
+
+    fn trash_entry(&self, item: &Location) -> Result<PathBuf> {
+        let batch_id = Uuid::new_v4().to_string();
+        self.trash_one(item, &batch_id, &NoProgress).map(|(slot, _)| slot)
+    }
+
+    fn restore_item(&self, trash_path: &Path, original: &Location) -> Result<()> {
+        if fs::symlink_metadata(trash_path).is_err() {
+            return Err(AppError::UndoFailed(format!(
+                "{} is no longer in the trash",
+                original.path().display()
+            )));
+        }
+
+        if original.exists() {
+            return Err(AppError::FileExists(original.path().to_string_lossy().to_string()));
+        }
+
+        if let Some(parent) = original.parent() {
+            parent.create_dir_all()?;
+        }
+
+        move_entry(&Location::ambient(trash_path)?, original, &NoProgress)?;
+
+        match fs::remove_file(info_path(trash_path)) {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn retrash_item(&self, item: &Location, trash_path: &Path, batch_id: &str) -> Result<()> {
+        if fs::symlink_metadata(trash_path).is_ok() {
+            return Err(AppError::FileExists(trash_path.to_string_lossy().to_string()));
+        }
+
+        let trash_dir = trash_path
+            .parent()
+            .and_then(Path::parent)
+            .ok_or_else(|| AppError::InvalidPath(trash_path.to_string_lossy().to_string()))?;
+        create_trash_dir(trash_dir)?;
+
+        let mut file = OpenOptions::new().write(true).create_new(true).open(info_path(trash_path))?;
+        write_info(&mut file, trash_dir, &item.path(), batch_id)?;
+
+        if let Err(e) = Location::ambient(trash_path).and_then(|dest| move_entry(item, &dest, &NoProgress)) {
+            let _ = fs::remove_file(info_path(trash_path));
+            return Err(e);
+        }
+
+        Ok(())
+    }
+
+    fn list_items(&self) -> Result<Vec<TrashItem>> {
+        let mut items = Vec::new();
+
+        for trash_dir in self.trash_dirs() {
+            let entries = match fs::read_dir(trash_dir.join("info")) {
+                Ok(entries) => entries,
+                Err(_) => continue,
+            };
+
+            items.extend(
+                entries
+                    .filter_map(|e| e.ok())
+                    .map(|e| e.path())
+                    .filter(|p| p.to_string_lossy().ends_with(INFO_EXT))
+                    .filter_map(|info_file| self.read_item(&trash_dir, &info_file)),
+            );
+        }
+
+        Ok(items)
+    }
+
+    /// Removes the item first, so a failure never leaves it without its info file.
+    fn purge_item(&self, item: &TrashItem) -> Result<()> {
+        let trash_path = PathBuf::from(&item.trash_path);
+        Location::ambient(&trash_path)?.remove()?;
+
+        match fs::remove_file(info_path(&trash_path)) {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Creates `dir` with its `files/` and `info/` subdirectories, private to the user.
+fn create_trash_dir(dir: &Path) -> Result<()> {
+    let mut builder = DirBuilder::new();
+    builder.recursive(true).mode(0o700);
+    builder.create(dir.join("files"))?;
+    builder.create(dir.join("info"))?;
+    Ok(())
+}
+
+/// `info/<name>.trashinfo` belonging to the `files/<name>` slot.
+fn info_path(slot: &Path) -> PathBuf {
+    let mut name = slot.file_name().unwrap_or_default().to_os_string();
+    name.push(INFO_EXT);
+    slot.parent()
+        .and_then(Path::parent)
+        .unwrap_or_else(|| Path::new(""))
+        .join("info")
+        .join(name)
+}
+
+fn write_info(file: &mut File, trash_dir: &Path, original: &Path, batch_id: &str) -> Result<()> {
+    // Mount trashes store paths relative to the mount, so they survive remounting elsewhere
+    let recorded = top_dir_of(trash_dir)
+        .and_then(|top_dir| original.strip_prefix(top_dir).ok())
+        .unwrap_or(original);
+
+    write!(
+        file,
+        "[Trash Info]\nPath={}\nDeletionDate={}\n{}={}\n",
+        encode_path(recorded),
+        Local::now().format(DATE_FORMAT),
+        BATCH_KEY,
+        batch_id
+    )?;
+    Ok(())
+}
+
+/// Top directory of a mount trash; `None` for the home trash.
+fn top_dir_of(trash_dir: &Path) -> Option<&Path> {
+    let name = trash_dir.file_name()?.to_string_lossy();
+    if name.starts_with(".Trash-") {
+        return trash_dir.parent();
+    }
+
+    let parent = trash_dir.parent()?;
+    if parent.file_name()? == ".Trash" {
+        return parent.parent();
+    }
+
+    None
+}
+
+/// Highest ancestor of `dir` that is still on `device`.
+fn mount_point(dir: &Path, device: u64) -> PathBuf {
+    let mut top_dir = dir;
+    for ancestor in dir.ancestors().skip(1) {
+        match fs::metadata(ancestor) {
+            Ok(m) if m.dev() == device => top_dir = ancestor,
+            _ => break,
+        }
+    }
+    top_dir.to_path_buf()
+}
+
+fn mount_points() -> Vec<PathBuf> {
+    let mounts = fs::read_to_string("/proc/self/mounts").unwrap_or_default();
+    mounts
+        .lines()
+        .filter_map(|line| line.split_whitespace().nth(1))
+        .map(|field| PathBuf::from(OsString::from_vec(unescape_mount_field(field))))
+        .collect()
+}
+
+/// Undoes the octal escapes ("\040" for a space) used in /proc/self/mounts.
+fn unescape_mount_field(field: &str) -> Vec<u8> {
+    let bytes = field.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let octal = bytes
+            .get(i + 1..i + 4)
+            .and_then(|digits| std::str::from_utf8(digits).ok())
+            .and_then(|digits| u8::from_str_radix(digits, 8).ok());
+        match (bytes[i], octal) {
+            (b'\\', Some(byte)) => {
+                out.push(byte);
+                i += 4;
+            }
+            (byte, _) => {
+                out.push(byte);
+                i += 1;
+            }
+        }
+    }
+    out
+}
+
+/// Percent-encodes a path for the `Path` key, leaving unreserved characters and '/'.
+fn encode_path(path: &Path) -> String {
+    let mut encoded = String::new();
+    for &byte in path.as_os_str().as_bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+fn decode_path(value: &str) -> PathBuf {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], escaped) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    PathBuf::from(OsString::from_vec(decoded))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trash_and_restore_round_trip() {
+        let root = std::env::temp_dir().join(format!("fm-xdg-{}", Uuid::new_v4()));
+        let trash = XdgTrash { home_trash: root.join("Trash"), uid: 1000, mount_points: Vec::new };
+        let original = root.join("docs").join("a b%.txt");
+        fs::create_dir_all(original.parent().unwrap()).unwrap();
+        fs::write(&original, "hello").unwrap();
+
+        let batch = trash
+            .soft_delete(&Sandbox::new(&[], false), vec![original.clone()], ConflictPolicy::default(), SymlinkPolicy::Preserve, &NoProgress)
+            .unwrap();
+        let slot = PathBuf::from(batch.results[0].dest_path.clone().unwrap());
+        assert!(trash.owns(&slot));
+        let info = fs::read_to_string(info_path(&slot)).unwrap();
+        assert!(info.contains("/docs/a%20b%25.txt\n"));
+
+        // Items other programs trashed are never listed, so never purged
+        fs::write(root.join("Trash/files/foreign.txt"), "theirs").unwrap();
+        fs::write(
+            root.join("Trash/info/foreign.txt.trashinfo"),
+            "[Trash Info]\nPath=/elsewhere/foreign.txt\nDeletionDate=2024-01-01T00:00:00\n",
+        )
+        .unwrap();
+
+        let items = trash.list_items().unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].original_path, original.to_string_lossy());
+        assert_eq!(items[0].batch_id, batch.batch_id);
+
+        trash.restore_item(&slot, &Location::ambient(&original).unwrap()).unwrap();
+        assert_eq!(fs::read_to_string(&original).unwrap(), "hello");
+        assert!(!info_path(&slot).exists());
+        assert!(trash.list_items().unwrap().is_empty());
+        assert!(root.join("Trash/files/foreign.txt").exists());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,222 @@
+use crate::db::{Database, IndexEntry, IndexRootStatus, IndexState};
+use crate::error::{AppError, Result};
+use crate::fs::operations::{NoProgress, Progress};
+use crate::fs::sandbox::{canonicalize_existing, Location, Sandbox};
+use crate::fs::FileItem;
+use cap_std::fs::Metadata;
+use chrono::{DateTime, Utc};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::UNIX_EPOCH;
+
+/// Entries written per transaction while crawling, so the database isn't
+/// locked for the whole crawl.
+const BATCH_SIZE: usize = 1000;
+
+/// Crawls roots into the filename index in `filemanager.db` and keeps the
+/// indexed entries current.
+#[derive(Default)]
+pub struct Indexer {
+    /// Roots being crawled right now
+    running: Mutex<HashSet<String>>,
+}
+
+impl Indexer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Crawls `root` and brings its entries up to date: new and changed
+    /// entries are written, entries that no longer exist are dropped. A
+    /// cancelled crawl keeps what it wrote but drops nothing. `root` is
+    /// crawled through its handle, so links out of it are recorded as links.
+    pub fn index_root(&self, db: &Database, root: &Location, progress: &dyn Progress) -> Result<IndexRootStatus> {
+        // Keyed by where a symlinked root leads, inside the roots
+        let root = root.follow()?;
+        if !root.metadata()?.is_dir() {
+            return Err(AppError::InvalidPath(format!("{} is not a directory", root.path().display())));
+        }
+        let root_key = root.path().to_string_lossy().to_string();
+
+        if !self.running.lock().unwrap().insert(root_key.clone()) {
+            return Err(AppError::InvalidState(format!("{} is already being indexed", root_key)));
+        }
+
+        let previous = db.get_index_root(&root_key).ok().flatten();
+        let last_indexed_at = previous.and_then(|p| p.last_indexed_at);
+        let mut status = IndexRootStatus {
+            root: root_key.clone(),
+            state: IndexState::Indexing,
+            entry_count: 0,
+            last_indexed_at: last_indexed_at.clone(),
+            message: None,
+        };
+        let _ = db.set_index_root(&status);
+
+        let seen = Utc::now().timestamp_millis();
+        let outcome = crawl(db, &root_key, &root, false, seen, progress).and_then(|complete| {
+            if complete {
+                db.remove_unseen_entries(&root_key, seen)?;
+            }
+            Ok(complete)
+        });
+        self.running.lock().unwrap().remove(&root_key);
+
+        match outcome {
+            Ok(true) => {
+                status.state = IndexState::Idle;
+                status.last_indexed_at = Some(Utc::now().to_rfc3339());
+            }
+            Ok(false) => {
+                status.state = IndexState::Idle;
+                status.message = Some("Cancelled; the index may be incomplete".to_string());
+            }
+            Err(e) => {
+                status.state = IndexState::Failed;
+                status.message = Some(e.to_string());
+            }
+        }
+        db.set_index_root(&status)?;
+
+        Ok(db.get_index_root(&root_key)?.unwrap_or(status))
+    }
+
+    /// Re-reads `paths` (and everything below them) after they were created,
+    /// changed, moved or removed. Paths outside every indexed root, or
+    /// outside the sandbox, are ignored.
+    pub fn refresh_paths(&self, db: &Database, sandbox: &Sandbox, paths: &[PathBuf]) -> Result<()> {
+        let roots = db.get_index_roots()?;
+        let seen = Utc::now().timestamp_millis();
+
+        for path in paths {
+            // Roots are kept canonical; the entry itself stays unresolved,
+            // as the crawl records links
+            let path = match (path.parent(), path.file_name()) {
+                (Some(parent), Some(name)) => canonicalize_existing(parent).map_or_else(|| path.clone(), |parent| parent.join(name)),
+                _ => path.clone(),
+            };
+            let root = roots
+                .iter()
+                .filter(|r| path.starts_with(&r.root))
+                .max_by_key(|r| r.root.len());
+            let (root, location) = match (root, sandbox.locate(&path)) {
+                (Some(root), Ok(location)) => (root, location),
+                _ => continue,
+            };
+
+            db.remove_index_path(&path.to_string_lossy())?;
+            if location.exists() {
+                crawl(db, &root.root, &location, path != Path::new(&root.root), seen, &NoProgress)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Writes every entry under `dir`, and `dir` itself if `include_dir` is
+/// set, to the index of `root`, stamped `seen`. Returns `false` if
+/// cancelled before the walk finished.
+fn crawl(db: &Database, root: &str, dir: &Location, include_dir: bool, seen: i64, progress: &dyn Progress) -> Result<bool> {
+    let mut batch = Vec::with_capacity(BATCH_SIZE);
+    // A link is recorded, never crawled through
+    let below = dir.symlink_metadata().is_ok_and(|m| m.is_dir()).then(|| dir.walk(None));
+    let entries = include_dir
+        .then(|| dir.clone())
+        .into_iter()
+        .chain(below.into_iter().flatten().map(|(entry, _)| entry));
+
+    for entry in entries {
+        if progress.is_cancelled() {
+            db.index_entries(root, &batch, seen)?;
+            return Ok(false);
+        }
+        let path = entry.path();
+        progress.item_started(&path);
+
+        if let Ok(metadata) = entry.symlink_metadata() {
+            batch.push(index_entry(&path, &metadata));
+        }
+        if batch.len() >= BATCH_SIZE {
+            db.index_entries(root, &batch, seen)?;
+            batch.clear();
+        }
+    }
+
+    db.index_entries(root, &batch, seen)?;
+    Ok(true)
+}
+
+fn index_entry(path: &Path, metadata: &Metadata) -> IndexEntry {
+    let mtime = metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.into_std().duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or_default();
+
+    IndexEntry {
+        path: path.to_string_lossy().to_string(),
+        name: path.file_name().unwrap_or_default().to_string_lossy().to_string(),
+        ext: if metadata.is_file() {
+            path.extension().map(|e| e.to_string_lossy().to_string())
+        } else {
+            None
+        },
+        size: metadata.len(),
+        mtime,
+        is_dir: metadata.is_dir(),
+        is_symlink: metadata.file_type().is_symlink(),
+    }
+}
+
+pub fn to_file_item(entry: IndexEntry) -> FileItem {
+    FileItem {
+        name: entry.name,
+        path: entry.path,
+        is_dir: entry.is_dir,
+        is_symlink: entry.is_symlink,
+        size: entry.size,
+        modified: DateTime::<Utc>::from_timestamp_millis(entry.mtime)
+            .map(|t| t.to_rfc3339())
+            .unwrap_or_default(),
+        ext: entry.ext,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::IndexFilters;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_refresh_through_linked_path() {
+        let base = std::env::temp_dir().join(format!("fm-index-{}", uuid::Uuid::new_v4()));
+        let (real, outside) = (base.join("real"), base.join("outside"));
+        std::fs::create_dir_all(&real).unwrap();
+        std::fs::create_dir_all(&outside).unwrap();
+        std::fs::write(outside.join("secret.txt"), "x").unwrap();
+        std::os::unix::fs::symlink(&real, base.join("link")).unwrap();
+        std::os::unix::fs::symlink(&outside, real.join("out")).unwrap();
+
+        let db = Database::new(":memory:").unwrap();
+        let indexer = Indexer::new();
+        indexer.index_root(&db, &Location::ambient(&real).unwrap(), &NoProgress).unwrap();
+
+        // Changes reported under another spelling of the root still land in it
+        std::fs::write(real.join("notes.txt"), "x").unwrap();
+        indexer.refresh_paths(&db, &Sandbox::new(&[], false), &[base.join("link/notes.txt")]).unwrap();
+        let found = db.search_index("notes", &IndexFilters::default()).unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(Path::new(&found[0].path), real.canonicalize().unwrap().join("notes.txt"));
+
+        // Links are recorded as links, not crawled through
+        let link = db.search_index("out", &IndexFilters::default()).unwrap();
+        assert!(link.len() == 1 && link[0].is_symlink);
+        assert!(db.search_index("secret", &IndexFilters::default()).unwrap().is_empty());
+
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+}
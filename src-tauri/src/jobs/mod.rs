@@ -0,0 +1,291 @@
+use crate::error::{AppError, Result};
+use crate::fs::operations::Progress;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter};
+use uuid::Uuid;
+
+/// Event carrying a `JobInfo` snapshot whenever a job makes progress.
+pub const JOB_PROGRESS_EVENT: &str = "job-progress";
+
+/// Finished jobs kept around for `list_jobs`.
+const MAX_FINISHED_JOBS: usize = 50;
+
+/// Minimum gap between two byte-level progress events of the same job.
+const EMIT_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Running,
+    Completed,
+    Cancelled,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobInfo {
+    pub id: String,
+    pub kind: String,
+    pub status: JobStatus,
+    pub items_done: usize,
+    pub items_total: usize,
+    pub bytes_done: u64,
+    pub bytes_total: u64,
+    pub current_path: Option<String>,
+    pub started_at: String,
+    pub finished_at: Option<String>,
+    pub batch_id: Option<String>,
+    pub message: Option<String>,
+}
+
+struct Job {
+    info: Mutex<JobInfo>,
+    cancelled: AtomicBool,
+    last_emit: Mutex<Instant>,
+}
+
+/// Tracks long-running operations so they can be listed and cancelled.
+#[derive(Default)]
+pub struct JobManager {
+    jobs: Mutex<HashMap<String, Arc<Job>>>,
+}
+
+impl JobManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a new running job of the given kind ("MOVE", "COPY", ...)
+    /// under `job_id`, or a new id when none is given. Callers that pass
+    /// their own id can cancel the job before the command returns.
+    pub fn start(&self, kind: &str, job_id: Option<String>, app: &AppHandle) -> Result<JobHandle> {
+        let job = self.register(kind, job_id)?;
+        let handle = JobHandle { job, app: Some(app.clone()) };
+        handle.emit();
+        Ok(handle)
+    }
+
+    /// A job whose progress goes nowhere.
+    #[cfg(test)]
+    fn start_detached(&self, kind: &str) -> JobHandle {
+        JobHandle { job: self.register(kind, None).unwrap(), app: None }
+    }
+
+    fn register(&self, kind: &str, job_id: Option<String>) -> Result<Arc<Job>> {
+        let info = JobInfo {
+            id: job_id.unwrap_or_else(|| Uuid::new_v4().to_string()),
+            kind: kind.to_string(),
+            status: JobStatus::Running,
+            items_done: 0,
+            items_total: 0,
+            bytes_done: 0,
+            bytes_total: 0,
+            current_path: None,
+            started_at: Utc::now().to_rfc3339(),
+            finished_at: None,
+            batch_id: None,
+            message: None,
+        };
+
+        let job = Arc::new(Job {
+            info: Mutex::new(info.clone()),
+            cancelled: AtomicBool::new(false),
+            last_emit: Mutex::new(Instant::now()),
+        });
+
+        let mut jobs = self.jobs.lock().unwrap();
+        if jobs.contains_key(&info.id) {
+            return Err(AppError::InvalidState(format!("Job id {} is already in use", info.id)));
+        }
+        Self::prune_finished(&mut jobs);
+        jobs.insert(info.id.clone(), job.clone());
+        Ok(job)
+    }
+
+    pub fn list(&self) -> Vec<JobInfo> {
+        let jobs = self.jobs.lock().unwrap();
+        let mut list: Vec<JobInfo> = jobs
+            .values()
+            .map(|job| job.info.lock().unwrap().clone())
+            .collect();
+        list.sort_by(|a, b| a.started_at.cmp(&b.started_at));
+        list
+    }
+
+    /// Asks a running job to stop. Items already processed stay processed.
+    pub fn cancel(&self, job_id: &str) -> Result<()> {
+        let jobs = self.jobs.lock().unwrap();
+        let job = jobs
+            .get(job_id)
+            .ok_or_else(|| AppError::NotFound(format!("No job with id {}", job_id)))?;
+
+        if job.info.lock().unwrap().status != JobStatus::Running {
+            return Err(AppError::InvalidState(format!("Job {} is not running", job_id)));
+        }
+
+        job.cancelled.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+
+    fn prune_finished(jobs: &mut HashMap<String, Arc<Job>>) {
+        let mut finished: Vec<(String, String)> = jobs
+            .iter()
+            .filter_map(|(id, job)| {
+                let info = job.info.lock().unwrap();
+                info.finished_at.clone().map(|at| (at, id.clone()))
+            })
+            .collect();
+
+        if finished.len() >= MAX_FINISHED_JOBS {
+            finished.sort();
+            for (_, id) in finished.iter().take(finished.len() + 1 - MAX_FINISHED_JOBS) {
+                jobs.remove(id);
+            }
+        }
+    }
+}
+
+/// Handle given to the worker running a job; reports progress as events.
+#[derive(Clone)]
+pub struct JobHandle {
+    job: Arc<Job>,
+    /// Where progress events go; `None` when nobody listens
+    app: Option<AppHandle>,
+}
+
+impl JobHandle {
+    pub fn id(&self) -> String {
+        self.job.info.lock().unwrap().id.clone()
+    }
+
+    /// Marks the job finished with the batch it produced, if any, or the error that stopped it.
+    pub fn finish(&self, outcome: std::result::Result<Option<String>, String>) {
+        {
+            let mut info = self.job.info.lock().unwrap();
+            info.finished_at = Some(Utc::now().to_rfc3339());
+            info.current_path = None;
+            match outcome {
+                Ok(batch_id) => {
+                    info.batch_id = batch_id;
+                    info.status = if self.is_cancelled() {
+                        JobStatus::Cancelled
+                    } else {
+                        JobStatus::Completed
+                    };
+                }
+                Err(message) => {
+                    info.status = JobStatus::Failed;
+                    info.message = Some(message);
+                }
+            }
+        }
+        self.emit();
+    }
+
+    fn update(&self, force_emit: bool, f: impl FnOnce(&mut JobInfo)) {
+        f(&mut self.job.info.lock().unwrap());
+
+        let mut last_emit = self.job.last_emit.lock().unwrap();
+        if force_emit || last_emit.elapsed() >= EMIT_INTERVAL {
+            *last_emit = Instant::now();
+            drop(last_emit);
+            self.emit();
+        }
+    }
+
+    fn emit(&self) {
+        if let Some(app) = &self.app {
+            let info = self.job.info.lock().unwrap().clone();
+            let _ = app.emit(JOB_PROGRESS_EVENT, info);
+        }
+    }
+}
+
+impl Progress for JobHandle {
+    fn begin(&self, items_total: usize, bytes_total: u64) {
+        self.update(true, |info| {
+            info.items_total = items_total;
+            info.bytes_total = bytes_total;
+        });
+    }
+
+    fn item_started(&self, path: &Path) {
+        self.update(false, |info| {
+            info.current_path = Some(path.to_string_lossy().to_string());
+        });
+    }
+
+    fn bytes_done(&self, bytes: u64) {
+        self.update(false, |info| info.bytes_done += bytes);
+    }
+
+    fn item_finished(&self) {
+        self.update(true, |info| info.items_done += 1);
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.job.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::conflict::ConflictPolicy;
+    use crate::fs::operations::{copy_items, SymlinkPolicy};
+    use crate::fs::sandbox::{AllowedRoot, RootMode, Sandbox};
+    use crate::fs::trash::TrashManager;
+    use std::fs;
+
+    /// Cancels its job through the manager once the first item is done.
+    struct CancelAfterFirst<'a> {
+        jobs: &'a JobManager,
+        job: JobHandle,
+    }
+
+    impl Progress for CancelAfterFirst<'_> {
+        fn item_finished(&self) {
+            self.job.item_finished();
+            let _ = self.jobs.cancel(&self.job.id());
+        }
+        fn is_cancelled(&self) -> bool {
+            self.job.is_cancelled()
+        }
+    }
+
+    #[test]
+    fn test_cancel_stops_batch() {
+        let root = std::env::temp_dir().join(format!("fm-jobs-{}", Uuid::new_v4()));
+        let (src, dest) = (root.join("src"), root.join("dest"));
+        fs::create_dir_all(&src).unwrap();
+        let paths: Vec<_> = ["a.txt", "b.txt", "c.txt"].iter().map(|name| src.join(name)).collect();
+        for path in &paths {
+            fs::write(path, "x").unwrap();
+        }
+
+        let sandbox = Sandbox::new(&[AllowedRoot { path: root.to_string_lossy().to_string(), mode: RootMode::ReadWrite }], false);
+        let trash = TrashManager::new(&root.join("app"));
+        let jobs = JobManager::new();
+        let job = jobs.start_detached("COPY");
+        let progress = CancelAfterFirst { jobs: &jobs, job: job.clone() };
+
+        let result = copy_items(&sandbox, paths, &dest, ConflictPolicy::Fail, &trash, SymlinkPolicy::Preserve, &progress).unwrap();
+        job.finish(Ok(Some(result.batch_id.clone())));
+
+        assert!(result.cancelled);
+        assert_eq!(result.processed, 1);
+        assert!(dest.join("a.txt").exists() && !dest.join("b.txt").exists() && !dest.join("c.txt").exists());
+        assert_eq!(jobs.list()[0].status, JobStatus::Cancelled);
+
+        assert!(matches!(jobs.cancel(&job.id()), Err(AppError::InvalidState(_))));
+        assert!(matches!(jobs.cancel("missing"), Err(AppError::NotFound(_))));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -2,11 +2,18 @@ mod commands;
 mod db;
 mod error;
 mod fs;
+mod index;
+mod jobs;
+mod watch;
 
 use commands::file_ops::AppState;
+use fs::sandbox::{RootSettings, Sandbox};
 use db::Database;
 use fs::trash::TrashManager;
-use std::sync::Mutex;
+use index::Indexer;
+use jobs::JobManager;
+use watch::WatchManager;
+use std::sync::{Arc, Mutex};
 use tauri::Manager;
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
@@ -22,14 +29,33 @@ pub fn run() {
             let db = Database::new(db_path.to_str().unwrap())?;
             
             let trash_manager = TrashManager::new(&app_dir);
+            if let Ok(Some(backend)) = db.get_setting(commands::TRASH_BACKEND_KEY) {
+                // A backend missing on this system leaves the default in place
+                let _ = trash_manager.set_backend(backend);
+            }
+            
+            let allowed_roots = RootSettings {
+                roots: db.get_allowed_roots()?,
+                strict: db.get_setting(commands::STRICT_ROOTS_KEY)?.unwrap_or(false),
+            };
+            
+            let log_retention = db.get_setting(commands::LOG_RETENTION_KEY)?.unwrap_or_default();
             
             let state = AppState {
                 db,
-                allowed_roots: Mutex::new(Vec::new()),
+                sandbox: Mutex::new(Arc::new(Sandbox::new(&allowed_roots.roots, allowed_roots.strict))),
+                allowed_roots: Mutex::new(allowed_roots),
                 trash_manager,
+                jobs: JobManager::new(),
+                indexer: Indexer::new(),
+                watcher: WatchManager::new(),
+                log_retention: Mutex::new(log_retention),
             };
             
             app.manage(state);
+            commands::spawn_trash_sweeper(app.handle().clone());
+            commands::spawn_index_refresher(app.handle().clone());
+            commands::spawn_log_pruner(app.handle().clone());
             
             Ok(())
         })
@@ -39,12 +65,42 @@ pub fn run() {
             commands::make_dir,
             commands::rename_path,
             commands::move_paths,
+            commands::copy_paths,
             commands::soft_delete,
+            commands::watch_dir,
+            commands::unwatch_dir,
+            commands::list_jobs,
+            commands::cancel_job,
             commands::undo_last_action,
+            commands::redo_last_action,
+            commands::undo_batch,
+            commands::list_trash,
+            commands::restore_trash_items,
+            commands::purge_trash_items,
+            commands::empty_trash,
+            commands::trash_usage,
+            commands::get_trash_retention,
+            commands::set_trash_retention,
+            commands::get_trash_backend,
+            commands::set_trash_backend,
             commands::search,
+            commands::search_contents,
+            commands::index_status,
+            commands::reindex_root,
+            commands::search_index,
             commands::get_favorites,
+            commands::get_allowed_roots,
             commands::set_allowed_roots,
             commands::get_recent_logs,
+            commands::query_logs,
+            commands::export_logs,
+            commands::import_logs,
+            commands::verify_log_integrity,
+            commands::get_access_logs,
+            commands::get_log_retention,
+            commands::set_log_retention,
+            commands::prune_logs,
+            commands::vacuum_db,
             commands::db_log,
         ])
         .run(tauri::generate_context!())
@@ -0,0 +1,188 @@
+use crate::commands::file_ops::AppState;
+use crate::commands::search_ops::refresh_index_in_background;
+use crate::error::{AppError, Result};
+use crate::fs::FileItem;
+use notify::event::{ModifyKind, RenameMode};
+use notify::{EventKind, RecommendedWatcher, RecursiveMode};
+use notify_debouncer_full::{new_debouncer, DebounceEventResult, DebouncedEvent, Debouncer, RecommendedCache};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager};
+
+/// Event carrying a `DirChanges` batch for a watched directory.
+pub const DIR_CHANGE_EVENT: &str = "dir-change";
+
+/// Quiet period before a burst of changes is reported.
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    Created,
+    Modified,
+    Removed,
+    Renamed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DirChange {
+    pub kind: ChangeKind,
+    pub path: String,
+    /// Previous path of a renamed entry
+    pub old_path: Option<String>,
+    /// Current state of the entry; `None` once it is gone
+    pub item: Option<FileItem>,
+}
+
+/// Changes in one watched directory, reported together after a quiet period.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DirChanges {
+    pub dir: String,
+    pub changes: Vec<DirChange>,
+}
+
+struct Watch {
+    _debouncer: Debouncer<RecommendedWatcher, RecommendedCache>,
+    /// `watch_dir` calls not yet matched by `unwatch_dir`
+    subscribers: usize,
+}
+
+/// Watches directories for changes made by other programs. Each directory
+/// has one OS watch however many views are showing it.
+#[derive(Default)]
+pub struct WatchManager {
+    watches: Mutex<HashMap<PathBuf, Watch>>,
+}
+
+impl WatchManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts reporting changes to the direct children of `dir`.
+    pub fn watch(&self, dir: &Path, app: &AppHandle) -> Result<()> {
+        let dir = dir.canonicalize()?;
+        if !dir.is_dir() {
+            return Err(AppError::InvalidPath(format!("{} is not a directory", dir.display())));
+        }
+
+        let mut watches = self.watches.lock().unwrap();
+        if let Some(watch) = watches.get_mut(&dir) {
+            watch.subscribers += 1;
+            return Ok(());
+        }
+
+        let handler_app = app.clone();
+        let handler_dir = dir.clone();
+        let mut debouncer = new_debouncer(DEBOUNCE, None, move |result: DebounceEventResult| {
+            if let Ok(events) = result {
+                report_changes(&handler_app, &handler_dir, events);
+            }
+        })
+        .map_err(|e| AppError::IoError(e.to_string()))?;
+
+        debouncer
+            .watch(&dir, RecursiveMode::NonRecursive)
+            .map_err(|e| AppError::IoError(e.to_string()))?;
+
+        watches.insert(dir, Watch { _debouncer: debouncer, subscribers: 1 });
+        Ok(())
+    }
+
+    /// Drops one subscription to `dir`; the OS watch goes with the last one.
+    pub fn unwatch(&self, dir: &Path) -> Result<()> {
+        let dir = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+
+        let mut watches = self.watches.lock().unwrap();
+        let watch = watches
+            .get_mut(&dir)
+            .ok_or_else(|| AppError::NotFound(format!("{} is not being watched", dir.display())))?;
+
+        watch.subscribers -= 1;
+        if watch.subscribers == 0 {
+            watches.remove(&dir);
+        }
+        Ok(())
+    }
+}
+
+/// Turns debounced notify events into `DirChange`s and emits them. Paths
+/// outside the allowed roots are dropped, as are all changes once the
+/// directory itself is no longer allowed.
+fn report_changes(app: &AppHandle, dir: &Path, events: Vec<DebouncedEvent>) {
+    let state = app.state::<AppState>();
+    let sandbox = state.sandbox();
+    if !sandbox.contains(dir) {
+        return;
+    }
+
+    let mut changes = Vec::new();
+    for event in events {
+        let kind = match event.kind {
+            EventKind::Create(_) => ChangeKind::Created,
+            EventKind::Remove(_) => ChangeKind::Removed,
+            EventKind::Modify(ModifyKind::Name(RenameMode::Both)) if event.paths.len() == 2 => ChangeKind::Renamed,
+            // One half of a move across the watch boundary
+            EventKind::Modify(ModifyKind::Name(RenameMode::From)) => ChangeKind::Removed,
+            EventKind::Modify(ModifyKind::Name(RenameMode::To)) => ChangeKind::Created,
+            EventKind::Modify(ModifyKind::Name(_)) => match event.paths.first() {
+                Some(path) if std::fs::symlink_metadata(path).is_ok() => ChangeKind::Created,
+                _ => ChangeKind::Removed,
+            },
+            EventKind::Modify(_) => ChangeKind::Modified,
+            _ => continue,
+        };
+
+        let (old_path, path) = match (kind, event.paths.as_slice()) {
+            (ChangeKind::Renamed, [from, to]) => (Some(from.clone()), to.clone()),
+            (_, [path, ..]) => (None, path.clone()),
+            _ => continue,
+        };
+
+        // Attribute changes of the watched directory itself aren't about its contents
+        if kind == ChangeKind::Modified && path == dir {
+            continue;
+        }
+
+        let item = match kind {
+            ChangeKind::Removed => None,
+            // Outside the roots, or gone again before we got to it, in
+            // which case a later event reports that
+            _ => match sandbox.locate(&path).and_then(|location| FileItem::from_location(&location)) {
+                Ok(item) => Some(item),
+                Err(_) => continue,
+            },
+        };
+
+        changes.push(DirChange {
+            kind,
+            path: path.to_string_lossy().to_string(),
+            old_path: old_path.map(|p| p.to_string_lossy().to_string()),
+            item,
+        });
+    }
+
+    if changes.is_empty() {
+        return;
+    }
+
+    // Keep the filename index in step with changes made outside the app,
+    // without holding up the event
+    let touched: Vec<PathBuf> = changes
+        .iter()
+        .flat_map(|c| std::iter::once(&c.path).chain(c.old_path.iter()))
+        .map(PathBuf::from)
+        .collect();
+    refresh_index_in_background(app, touched);
+
+    let _ = app.emit(
+        DIR_CHANGE_EVENT,
+        DirChanges {
+            dir: dir.to_string_lossy().to_string(),
+            changes,
+        },
+    );
+}
@@ -16,15 +16,22 @@ import { MoveDialog } from "./components/dialogs/MoveDialog";
 import "./styles/globals.css";
 
 function App() {
-  const { roots, loadFavorites, setCurrentPath, loadDirectory } = useFileStore();
+  const { roots, rootsLoaded, loadRoots, loadFavorites, setCurrentPath, loadDirectory } = useFileStore();
   const { isRootPickerOpen } = useUIStore();
   const [isInitialized, setIsInitialized] = useState(false);
 
   useEffect(() => {
-    // Load favorites on mount
+    // Load favorites and the stored roots on mount
     loadFavorites();
-    
-    // Check if we need to show root picker
+    loadRoots();
+  }, []);
+
+  useEffect(() => {
+    if (!rootsLoaded) {
+      return;
+    }
+
+    // Only ask for roots when none are stored yet
     if (roots.length === 0 && !isInitialized) {
       useUIStore.getState().openRootPicker();
     } else if (roots.length > 0 && !isInitialized) {
@@ -33,7 +40,11 @@ function App() {
       loadDirectory(roots[0]);
       setIsInitialized(true);
     }
-  }, [roots, isInitialized]);
+  }, [roots, rootsLoaded, isInitialized]);
+
+  if (!rootsLoaded) {
+    return null;
+  }
 
   if (isRootPickerOpen || (roots.length === 0 && !isInitialized)) {
     return <RootPicker />;
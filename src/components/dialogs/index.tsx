@@ -257,7 +257,7 @@ export const DeleteDialog: React.FC = () => {
 // Move Dialog
 export const MoveDialog: React.FC = () => {
   const { isMoveOpen, closeMoveDialog, moveDestination, setMoveDestination } = useUIStore();
-  const { selection, items, roots, favorites, refreshCurrentDirectory, clearSelection, currentPath } = useFileStore();
+  const { selection, items, roots, favorites, refreshCurrentDirectory, clearSelection, currentPath, listOptions } = useFileStore();
   const [loading, setLoading] = useState(false);
   const [error, setError] = useState('');
   const [expandedFolders, setExpandedFolders] = useState<Set<string>>(new Set());
@@ -267,7 +267,7 @@ export const MoveDialog: React.FC = () => {
 
   const loadFolderContents = async (path: string) => {
     try {
-      const contents = await commands.listDir(path);
+      const { items: contents } = await commands.listDir(path, listOptions());
       setFolderContents(prev => ({ ...prev, [path]: contents.filter(item => item.isDir) }));
     } catch (err) {
       console.error('Failed to load folder contents:', err);
@@ -89,7 +89,7 @@ export const Sidebar: React.FC = () => {
 
 // ============ TopBar Component ============
 export const TopBar: React.FC = () => {
-  const { currentPath, searchQuery, setSearchQuery, loadDirectory } = useFileStore();
+  const { currentPath, searchQuery, search, loadDirectory } = useFileStore();
   const { openCreateDialog } = useUIStore();
   const [localSearch, setLocalSearch] = useState('');
 
@@ -101,7 +101,7 @@ export const TopBar: React.FC = () => {
 
   const handleSearch = (e: React.FormEvent) => {
     e.preventDefault();
-    setSearchQuery(localSearch);
+    search(localSearch);
   };
 
   return (
@@ -241,12 +241,11 @@ export const Toolbar: React.FC = () => {
 
 // ============ FileTable Component ============
 export const FileTable: React.FC = () => {
-  const { items, selection, toggleSelection, clearSelection, selectAll, loading, error, searchQuery } = useFileStore();
+  const { items, selection, toggleSelection, clearSelection, selectAll, loading, error, searchQuery, searchResults, searchJobId } = useFileStore();
   const { viewMode, openRenameDialog } = useUIStore();
   
-  const filteredItems = searchQuery 
-    ? items.filter(item => item.name.toLowerCase().includes(searchQuery.toLowerCase()))
-    : items;
+  // Search results stream in from the whole tree under the current folder
+  const filteredItems = searchQuery ? searchResults : items;
 
   const handleDoubleClick = (item: FileItem) => {
     if (item.isDir) {
@@ -288,7 +287,9 @@ export const FileTable: React.FC = () => {
     return (
       <div className="flex items-center justify-center h-full">
         <div className="text-muted-foreground">
-          {searchQuery ? 'No files found matching your search' : 'This folder is empty'}
+          {searchQuery
+            ? (searchJobId ? 'Searching...' : 'No files found matching your search')
+            : 'This folder is empty'}
         </div>
       </div>
     );
@@ -479,9 +480,11 @@ export const RightDrawer: React.FC = () => {
 
 // ============ RootPicker Component ============
 export const RootPicker: React.FC = () => {
-  const { setRoots, favorites } = useFileStore();
+  const { roots, setRoots, favorites } = useFileStore();
   const { closeRootPicker } = useUIStore();
-  const [selectedRoots, setSelectedRoots] = useState<string[]>([]);
+  // Start from the roots already allowed, so reopening the picker keeps them
+  const [selectedRoots, setSelectedRoots] = useState<string[]>(roots);
+  const choices = [...roots, ...favorites.filter((path) => !roots.includes(path))];
 
   const handleSelect = (path: string) => {
     if (selectedRoots.includes(path)) {
@@ -507,7 +510,7 @@ export const RootPicker: React.FC = () => {
         </p>
         
         <div className="space-y-2 mb-6">
-          {favorites.map((path) => {
+          {choices.map((path) => {
             const name = getFileName(path) || path;
             return (
               <label
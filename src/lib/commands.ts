@@ -1,17 +1,44 @@
 import { invoke } from '@tauri-apps/api/core';
+import { listen, type UnlistenFn } from '@tauri-apps/api/event';
 import type {
+  AccessLog,
+  AllowedRoot,
   FileItem,
   FileStat,
+  DirPage,
   DirResult,
   RenameResult,
   BatchResult,
+  ConflictPolicy,
+  ContentSearchOptions,
+  ContentSearchResult,
+  IndexFilters,
+  ImportSummary,
+  IndexRootStatus,
+  JobInfo,
+  ListOptions,
+  LogFilters,
+  LogFormat,
+  LogIntegrity,
+  LogPage,
+  LogRetention,
+  PruneSummary,
+  RootSettings,
+  SearchChunk,
+  SearchFilters,
+  SymlinkPolicy,
+  TrashBackendKind,
+  TrashItem,
+  TrashRetention,
+  TrashUsage,
   UndoResult,
+  VacuumResult,
   ActionLog
 } from './types';
 
 export const commands = {
-  async listDir(path: string): Promise<FileItem[]> {
-    return invoke('list_dir', { path });
+  async listDir(path: string, options?: ListOptions): Promise<DirPage> {
+    return invoke('list_dir', { path, options });
   },
 
   async statPath(path: string): Promise<FileStat> {
@@ -26,34 +53,166 @@ export const commands = {
     return invoke('rename_path', { src, newName });
   },
 
-  async movePaths(srcPaths: string[], destDir: string): Promise<BatchResult> {
-    return invoke('move_paths', { srcPaths, destDir });
+  /** With a `jobId` of the caller's choosing, the move can be cancelled with `cancelJob` while it runs. */
+  async movePaths(srcPaths: string[], destDir: string, conflictPolicy?: ConflictPolicy, symlinkPolicy?: SymlinkPolicy, jobId?: string): Promise<BatchResult> {
+    return invoke('move_paths', { srcPaths, destDir, conflictPolicy, symlinkPolicy, jobId });
   },
 
-  async softDelete(paths: string[]): Promise<BatchResult> {
-    return invoke('soft_delete', { paths });
+  async copyPaths(srcPaths: string[], destDir: string, conflictPolicy?: ConflictPolicy, symlinkPolicy?: SymlinkPolicy, jobId?: string): Promise<BatchResult> {
+    return invoke('copy_paths', { srcPaths, destDir, conflictPolicy, symlinkPolicy, jobId });
+  },
+
+  async softDelete(paths: string[], conflictPolicy?: ConflictPolicy, symlinkPolicy?: SymlinkPolicy, jobId?: string): Promise<BatchResult> {
+    return invoke('soft_delete', { paths, conflictPolicy, symlinkPolicy, jobId });
+  },
+
+  /** Starts `dir-change` events for `path`; pair every call with `unwatchDir`. */
+  async watchDir(path: string): Promise<void> {
+    return invoke('watch_dir', { path });
+  },
+
+  async unwatchDir(path: string): Promise<void> {
+    return invoke('unwatch_dir', { path });
+  },
+
+  async listJobs(): Promise<JobInfo[]> {
+    return invoke('list_jobs');
+  },
+
+  async cancelJob(jobId: string): Promise<void> {
+    return invoke('cancel_job', { jobId });
   },
 
   async undoLastAction(): Promise<UndoResult> {
     return invoke('undo_last_action');
   },
 
-  async search(currentPath: string, query: string): Promise<FileItem[]> {
-    return invoke('search', { currentPath, query });
+  async redoLastAction(): Promise<UndoResult> {
+    return invoke('redo_last_action');
+  },
+
+  async undoBatch(batchId: string, dryRun?: boolean): Promise<UndoResult> {
+    return invoke('undo_batch', { batchId, dryRun });
+  },
+
+  async listTrash(): Promise<TrashItem[]> {
+    return invoke('list_trash');
+  },
+
+  async restoreTrashItems(ids: string[], conflictPolicy?: ConflictPolicy): Promise<BatchResult> {
+    return invoke('restore_trash_items', { ids, conflictPolicy });
+  },
+
+  async purgeTrashItems(ids: string[]): Promise<BatchResult> {
+    return invoke('purge_trash_items', { ids });
+  },
+
+  async emptyTrash(): Promise<BatchResult> {
+    return invoke('empty_trash');
+  },
+
+  async trashUsage(): Promise<TrashUsage> {
+    return invoke('trash_usage');
+  },
+
+  async getTrashRetention(): Promise<TrashRetention> {
+    return invoke('get_trash_retention');
+  },
+
+  async setTrashRetention(retention: TrashRetention): Promise<void> {
+    return invoke('set_trash_retention', { retention });
+  },
+
+  async getTrashBackend(): Promise<TrashBackendKind> {
+    return invoke('get_trash_backend');
+  },
+
+  async setTrashBackend(backend: TrashBackendKind): Promise<void> {
+    return invoke('set_trash_backend', { backend });
+  },
+
+  /**
+   * Starts a search and resolves with its job id; matches arrive as `search-results` events,
+   * possibly before this resolves, so pass a `jobId` to recognize them from the start.
+   */
+  async search(currentPath: string, query: string, filters?: SearchFilters, jobId?: string): Promise<string> {
+    return invoke('search', { currentPath, query, filters, jobId });
+  },
+
+  async onSearchResults(handler: (chunk: SearchChunk) => void): Promise<UnlistenFn> {
+    return listen<SearchChunk>('search-results', (event) => handler(event.payload));
+  },
+
+  async searchContents(currentPath: string, query: string, options?: ContentSearchOptions): Promise<ContentSearchResult> {
+    return invoke('search_contents', { currentPath, query, options });
+  },
+
+  async indexStatus(): Promise<IndexRootStatus[]> {
+    return invoke('index_status');
+  },
+
+  async reindexRoot(root: string): Promise<IndexRootStatus> {
+    return invoke('reindex_root', { root });
+  },
+
+  async searchIndex(query: string, filters?: IndexFilters): Promise<FileItem[]> {
+    return invoke('search_index', { query, filters });
   },
 
   async getFavorites(): Promise<string[]> {
     return invoke('get_favorites');
   },
 
-  async setAllowedRoots(roots: string[]): Promise<void> {
-    return invoke('set_allowed_roots', { roots });
+  async getAllowedRoots(): Promise<RootSettings> {
+    return invoke('get_allowed_roots');
+  },
+
+  async setAllowedRoots(roots: AllowedRoot[], strict?: boolean): Promise<void> {
+    return invoke('set_allowed_roots', { roots, strict });
   },
 
   async getRecentLogs(limit: number): Promise<ActionLog[]> {
     return invoke('get_recent_logs', { limit });
   },
 
+  /** Newest first; pass `next_cursor` of a page to get the one after it. */
+  async queryLogs(filters?: LogFilters, cursor?: number, limit?: number): Promise<LogPage> {
+    return invoke('query_logs', { filters, cursor, limit });
+  },
+
+  /** Resolves with the number of rows written to `path`. */
+  async exportLogs(path: string, format: LogFormat, filters?: LogFilters): Promise<number> {
+    return invoke('export_logs', { path, format, filters });
+  },
+
+  async importLogs(path: string, format: LogFormat): Promise<ImportSummary> {
+    return invoke('import_logs', { path, format });
+  },
+
+  async verifyLogIntegrity(): Promise<LogIntegrity> {
+    return invoke('verify_log_integrity');
+  },
+
+  async getAccessLogs(limit: number): Promise<AccessLog[]> {
+    return invoke('get_access_logs', { limit });
+  },
+
+  async getLogRetention(): Promise<LogRetention> {
+    return invoke('get_log_retention');
+  },
+
+  async setLogRetention(retention: LogRetention): Promise<void> {
+    return invoke('set_log_retention', { retention });
+  },
+
+  async pruneLogs(): Promise<PruneSummary> {
+    return invoke('prune_logs');
+  },
+
+  async vacuumDb(): Promise<VacuumResult> {
+    return invoke('vacuum_db');
+  },
+
   async dbLog(entry: Omit<ActionLog, 'id'>): Promise<number> {
     return invoke('db_log', { entry });
   },
@@ -2,11 +2,32 @@ export interface FileItem {
   name: string;
   path: string;
   isDir: boolean;
+  isSymlink: boolean;
   size: number;
   modified: string;
   ext?: string;
 }
 
+export type SortKey = 'name' | 'size' | 'modified' | 'ext';
+export type SortDirection = 'asc' | 'desc';
+
+export interface ListOptions {
+  sort_by?: SortKey;
+  direction?: SortDirection;
+  dirs_first?: boolean;
+  /** Defaults to true. */
+  show_hidden?: boolean;
+  offset?: number;
+  limit?: number;
+}
+
+export interface DirPage {
+  items: FileItem[];
+  total: number;
+  offset: number;
+  next_offset?: number;
+}
+
 export interface FileStat {
   name: string;
   path: string;
@@ -14,10 +35,23 @@ export interface FileStat {
   size: number;
   modified: string;
   created: string;
+  accessed: string;
+  isSymlink: boolean;
+  symlink_target?: string;
+  /** `ls -l` style, e.g. `drwxr-xr-x`; Unix only */
   permissions?: string;
+  /** Permission bits in octal, e.g. `0755`; Unix only */
+  mode?: string;
+  uid?: number;
+  gid?: number;
+  owner?: string;
+  group?: string;
+  inode?: number;
+  nlink?: number;
+  mime_type?: string;
 }
 
-export type ActionType = 'MOVE' | 'RENAME' | 'CREATE_DIR' | 'DELETE' | 'UNDO';
+export type ActionType = 'MOVE' | 'COPY' | 'RENAME' | 'CREATE_DIR' | 'DELETE' | 'UNDO' | 'REDO' | 'RESTORE' | 'PURGE' | 'LIST_DIR' | 'STAT' | 'CANCEL_JOB' | 'SET_ALLOWED_ROOTS';
 export type ActionStatus = 'SUCCESS' | 'ERROR';
 
 export interface ActionLog {
@@ -29,6 +63,75 @@ export interface ActionLog {
   status: ActionStatus;
   message?: string;
   batch_id?: string;
+  undone?: boolean;
+  /** Only backend rows take part in undo and redo. */
+  origin?: LogOrigin;
+  /** `AppError` code of an `ERROR` row. */
+  error_code?: string;
+}
+
+/** Entries sent through `dbLog` are stored as `client`. */
+export type LogOrigin = 'backend' | 'client' | 'imported';
+
+export interface LogIntegrity {
+  checked: number;
+  /** First row whose hash doesn't match, absent if the chain is intact. */
+  first_broken_id?: number;
+  message?: string;
+}
+
+export type LogFormat = 'jsonl' | 'csv';
+
+export interface ImportSummary {
+  imported: number;
+  /** Rows already in the log, matched by batch id and timestamp. */
+  skipped: number;
+}
+
+/** How successful read-only events such as directory listings are recorded. */
+export type AccessLogging = { mode: 'off' } | { mode: 'access_log' } | { mode: 'sampled'; every: number };
+
+/** Limits enforced by the log pruner; absent means unlimited. The newest 100 action log rows are always kept. */
+export interface LogRetention {
+  access: AccessLogging;
+  max_age_days?: number;
+  /** Rows kept per table, newest first. */
+  max_rows?: number;
+}
+
+export interface AccessLog {
+  id: number;
+  timestamp: string;
+  action: string;
+  path: string;
+  /** The row stands for this many events when sampling. */
+  sample_every: number;
+}
+
+export interface PruneSummary {
+  action_logs: number;
+  access_logs: number;
+}
+
+export interface VacuumResult {
+  bytes_before: number;
+  bytes_after: number;
+}
+
+/** Filters for `queryLogs`; everything left out matches. Times are RFC 3339 and inclusive. */
+export interface LogFilters {
+  actions?: ActionType[];
+  statuses?: ActionStatus[];
+  path_prefix?: string;
+  batch_id?: string;
+  since?: string;
+  until?: string;
+}
+
+export interface LogPage {
+  logs: ActionLog[];
+  total: number;
+  next_cursor?: number;
 }
 
 export interface DirResult {
@@ -44,23 +147,176 @@ export interface RenameResult {
   message?: string;
 }
 
+export type ConflictPolicy = 'skip' | 'overwrite' | 'keep_both' | 'fail';
+
+/** What copy, move and trash do with symlinks; links are never followed out of the allowed roots. */
+export type SymlinkPolicy = 'preserve' | 'follow' | 'skip';
+export type ConflictResolution = 'no_conflict' | 'skipped' | 'overwritten' | 'renamed' | 'failed';
+
 export interface BatchResult {
   success: boolean;
   processed: number;
   failed: number;
+  skipped: number;
+  cancelled: boolean;
   batch_id: string;
+  /** Job the batch ran as. */
+  job_id?: string;
   results: Array<{
     path: string;
     success: boolean;
     message?: string;
+    dest_path?: string;
+    resolution: ConflictResolution;
+    /** `AppError` code of a failed item. */
+    error_code?: string;
+    /** Trash location of the entry an overwrite replaced. */
+    replaced_path?: string;
   }>;
 }
 
+export type JobStatus = 'running' | 'completed' | 'cancelled' | 'failed';
+
+// Payload of the `job-progress` event and entries of `list_jobs`
+export interface JobInfo {
+  id: string;
+  kind: string;
+  status: JobStatus;
+  items_done: number;
+  items_total: number;
+  bytes_done: number;
+  bytes_total: number;
+  current_path?: string;
+  started_at: string;
+  finished_at?: string;
+  batch_id?: string;
+  message?: string;
+}
+
+export interface UndoItemResult {
+  log_id?: number;
+  action: ActionType;
+  path?: string;
+  success: boolean;
+  message?: string;
+}
+
 export interface UndoResult {
   success: boolean;
   action: ActionType;
   items_restored: number;
   message?: string;
+  items: UndoItemResult[];
+}
+
+export type TrashBackendKind = 'app' | 'xdg';
+
+export interface TrashItem {
+  id: string;
+  name: string;
+  original_path: string;
+  trash_path: string;
+  isDir: boolean;
+  size: number;
+  deleted_at: string;
+  batch_id: string;
+  backend: TrashBackendKind;
+}
+
+export interface TrashRetention {
+  max_age_days?: number;
+  max_total_bytes?: number;
+  max_items?: number;
+}
+
+export interface TrashUsage {
+  total_bytes: number;
+  item_count: number;
+  batch_count: number;
+  oldest_deleted_at?: string;
+}
+
+export type NameMatch = 'substring' | 'glob' | 'regex';
+export type EntryKind = 'file' | 'dir';
+
+export interface SearchFilters {
+  match_mode?: NameMatch;
+  case_sensitive?: boolean;
+  max_depth?: number;
+  extensions?: string[];
+  min_size?: number;
+  max_size?: number;
+  modified_after?: string;
+  modified_before?: string;
+  kind?: EntryKind;
+}
+
+/** Payload of the `search-results` event. */
+export interface SearchChunk {
+  job_id: string;
+  items: FileItem[];
+  done: boolean;
+  total?: number;
+}
+
+export interface ContentSearchOptions {
+  regex?: boolean;
+  case_sensitive?: boolean;
+  max_depth?: number;
+  max_file_size?: number;
+  max_results?: number;
+}
+
+export interface ContentMatch {
+  path: string;
+  line: number;
+  column: number;
+  snippet: string;
+  encoding: string;
+}
+
+export interface ContentSearchResult {
+  matches: ContentMatch[];
+  files_searched: number;
+  files_skipped: number;
+  truncated: boolean;
+  cancelled: boolean;
+}
+
+export type IndexState = 'idle' | 'indexing' | 'failed';
+
+export interface IndexRootStatus {
+  root: string;
+  state: IndexState;
+  entry_count: number;
+  last_indexed_at?: string;
+  message?: string;
+}
+
+export interface IndexFilters {
+  under?: string;
+  extensions?: string[];
+  min_size?: number;
+  max_size?: number;
+  modified_after?: string;
+  modified_before?: string;
+  kind?: EntryKind;
+  limit?: number;
+}
+
+export type ChangeKind = 'created' | 'modified' | 'removed' | 'renamed';
+
+export interface DirChange {
+  kind: ChangeKind;
+  path: string;
+  old_path?: string;
+  item?: FileItem;
+}
+
+/** Payload of the `dir-change` event. */
+export interface DirChanges {
+  dir: string;
+  changes: DirChange[];
 }
 
 export interface AppError {
@@ -69,6 +325,20 @@ export interface AppError {
   details?: any;
 }
 
+/** What file operations may do inside an allowed root. */
+export type RootMode = 'read_only' | 'read_write' | 'no_delete';
+
+export interface AllowedRoot {
+  path: string;
+  mode: RootMode;
+}
+
+/** With `strict` set, an empty root list denies all access instead of allowing everything. */
+export interface RootSettings {
+  roots: AllowedRoot[];
+  strict: boolean;
+}
+
 export interface RootConfig {
   roots: string[];
   favorites: string[];
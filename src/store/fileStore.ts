@@ -1,9 +1,13 @@
 import { create } from 'zustand';
-import { FileItem, ActionLog } from '../lib/types';
+import { FileItem, ActionLog, AllowedRoot, ListOptions } from '../lib/types';
 import { commands } from '../lib/commands';
+import type { UnlistenFn } from '@tauri-apps/api/event';
 
 interface FileState {
   roots: string[];
+  /** The stored roots with their modes, as the backend has them. */
+  allowedRoots: AllowedRoot[];
+  rootsLoaded: boolean;
   currentPath: string;
   items: FileItem[];
   selection: Set<string>;
@@ -12,10 +16,15 @@ interface FileState {
   loading: boolean;
   error: string | null;
   searchQuery: string;
+  /** Matches of the running or last search under `currentPath`. */
+  searchResults: FileItem[];
+  searchJobId: string | null;
   sortBy: 'name' | 'size' | 'modified' | 'type';
   sortOrder: 'asc' | 'desc';
+  showHidden: boolean;
 
   // Actions
+  loadRoots: () => Promise<void>;
   setRoots: (roots: string[]) => void;
   setCurrentPath: (path: string) => void;
   loadDirectory: (path: string) => Promise<void>;
@@ -25,15 +34,24 @@ interface FileState {
   clearSelection: () => void;
   selectAll: () => void;
   setSearchQuery: (query: string) => void;
+  search: (query: string) => Promise<void>;
   setSortBy: (sortBy: 'name' | 'size' | 'modified' | 'type') => void;
   setSortOrder: (order: 'asc' | 'desc') => void;
+  setShowHidden: (showHidden: boolean) => void;
+  /** Options every listing is requested with, following the sort and hidden-file settings. */
+  listOptions: () => ListOptions;
   loadFavorites: () => Promise<void>;
   loadRecentLogs: (limit?: number) => Promise<void>;
   refreshCurrentDirectory: () => Promise<void>;
 }
 
+/** Ends the listener of the current search, for when it is cleared. */
+let stopSearchListener: (() => void) | null = null;
+
 export const useFileStore = create<FileState>((set, get) => ({
   roots: [],
+  allowedRoots: [],
+  rootsLoaded: false,
   currentPath: '',
   items: [],
   selection: new Set(),
@@ -42,12 +60,31 @@ export const useFileStore = create<FileState>((set, get) => ({
   loading: false,
   error: null,
   searchQuery: '',
+  searchResults: [],
+  searchJobId: null,
   sortBy: 'name',
   sortOrder: 'asc',
+  showHidden: true,
+
+  loadRoots: async () => {
+    try {
+      const { roots } = await commands.getAllowedRoots();
+      set({ allowedRoots: roots, roots: roots.map((root) => root.path), rootsLoaded: true });
+    } catch (error) {
+      console.error('Failed to load allowed roots:', error);
+      set({ rootsLoaded: true });
+    }
+  },
 
   setRoots: (roots) => {
-    set({ roots });
-    commands.setAllowedRoots(roots);
+    // Roots that were already allowed keep their mode
+    const { allowedRoots } = get();
+    const updated: AllowedRoot[] = roots.map((path) => ({
+      path,
+      mode: allowedRoots.find((root) => root.path === path)?.mode ?? 'read_write',
+    }));
+    set({ roots, allowedRoots: updated });
+    commands.setAllowedRoots(updated);
   },
 
   setCurrentPath: (path) => {
@@ -55,9 +92,14 @@ export const useFileStore = create<FileState>((set, get) => ({
   },
 
   loadDirectory: async (path) => {
+    // Searches are run from one folder; leaving it ends them
+    if (path !== get().currentPath) {
+      get().search('');
+    }
     set({ loading: true, error: null });
     try {
-      const items = await commands.listDir(path);
+      // The whole directory, sorted by the backend
+      const { items } = await commands.listDir(path, get().listOptions());
       set({ 
         items, 
         currentPath: path, 
@@ -109,12 +151,84 @@ export const useFileStore = create<FileState>((set, get) => ({
     set({ searchQuery: query });
   },
 
+  search: async (query) => {
+    const { currentPath, searchJobId } = get();
+    if (searchJobId) {
+      commands.cancelJob(searchJobId).catch(() => {});
+    }
+    if (!query) {
+      stopSearchListener?.();
+      stopSearchListener = null;
+      set({ searchQuery: '', searchResults: [], searchJobId: null });
+      return;
+    }
+
+    // Known before the search starts, so no chunk of it is missed
+    const jobId = crypto.randomUUID();
+    set({ searchQuery: query, searchResults: [], searchJobId: jobId, error: null });
+
+    let unlisten: UnlistenFn | null = null;
+    const stopListening = () => {
+      unlisten?.();
+      unlisten = null;
+    };
+    unlisten = await commands.onSearchResults((chunk) => {
+      if (chunk.job_id !== jobId) {
+        return;
+      }
+      // A replaced search still ends with its last chunk, which ends its listener too
+      if (chunk.done) {
+        stopListening();
+      }
+      if (get().searchJobId !== jobId) {
+        return;
+      }
+      set((state) => ({ searchResults: [...state.searchResults, ...chunk.items] }));
+      if (chunk.done) {
+        set({ searchJobId: null });
+      }
+    });
+
+    // Replaced or cleared while the listener was being set up
+    if (get().searchJobId !== jobId) {
+      stopListening();
+      return;
+    }
+    stopSearchListener = stopListening;
+
+    try {
+      await commands.search(currentPath, query, undefined, jobId);
+    } catch (error) {
+      stopListening();
+      set({
+        searchJobId: null,
+        error: error instanceof Error ? error.message : 'Search failed',
+      });
+    }
+  },
+
   setSortBy: (sortBy) => {
     set({ sortBy });
+    get().refreshCurrentDirectory();
   },
 
   setSortOrder: (order) => {
     set({ sortOrder: order });
+    get().refreshCurrentDirectory();
+  },
+
+  setShowHidden: (showHidden) => {
+    set({ showHidden });
+    get().refreshCurrentDirectory();
+  },
+
+  listOptions: () => {
+    const { sortBy, sortOrder, showHidden } = get();
+    return {
+      sort_by: sortBy === 'type' ? 'ext' : sortBy,
+      direction: sortOrder,
+      show_hidden: showHidden,
+    };
   },
 
   loadFavorites: async () => {